@@ -1,90 +1,654 @@
+use nix::errno::Errno;
+use nix::sys::signal::Signal;
 use nix::sys::wait::{WaitPidFlag, WaitStatus, waitpid};
 use nix::unistd::Pid;
+use std::time::{Duration, Instant};
 
-#[derive(Clone)]
+use crate::job_control::signal_job;
+use crate::signals::{describe_signal, take_interrupt};
+
+/// How many finished jobs are remembered for `wait` after they were reported
+const COMPLETED_LIMIT: usize = 64;
+
+/// How a process ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// Exited with the given status
+    Code(i32),
+    /// Killed by the given signal, possibly dumping core
+    Signal(Signal, bool),
+}
+
+/// Where a job, or one process of it, is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    /// Suspended by the given signal, e.g. SIGTSTP from Ctrl-Z
+    Stopped(Signal),
+    Done(Exit),
+}
+
+impl JobState {
+    /// The state a wait status reports, or None if nothing changed
+    pub fn from_wait(status: WaitStatus) -> Option<Self> {
+        match status {
+            WaitStatus::Exited(_, code) => Some(JobState::Done(Exit::Code(code))),
+            WaitStatus::Signaled(_, signal, core_dumped) => Some(JobState::Done(Exit::Signal(signal, core_dumped))),
+            WaitStatus::Stopped(_, signal) => Some(JobState::Stopped(signal)),
+            WaitStatus::Continued(_) => Some(JobState::Running),
+            _ => None,
+        }
+    }
+
+    /// The exit status `$?` reports for a process in this state
+    pub fn status(self) -> i32 {
+        match self {
+            JobState::Running => 0,
+            JobState::Stopped(signal) | JobState::Done(Exit::Signal(signal, _)) => 128 + signal as i32,
+            JobState::Done(Exit::Code(code)) => code,
+        }
+    }
+
+    /// How bash's `jobs` describes the state, e.g. `Exit 2` or `Killed`
+    pub fn describe(self) -> String {
+        match self {
+            JobState::Running => "Running".to_string(),
+            JobState::Stopped(Signal::SIGSTOP) => "Stopped (signal)".to_string(),
+            JobState::Stopped(Signal::SIGTTIN) => "Stopped (tty input)".to_string(),
+            JobState::Stopped(Signal::SIGTTOU) => "Stopped (tty output)".to_string(),
+            JobState::Stopped(_) => "Stopped".to_string(),
+            JobState::Done(Exit::Code(0)) => "Done".to_string(),
+            JobState::Done(Exit::Code(code)) => format!("Exit {}", code),
+            JobState::Done(Exit::Signal(signal, false)) => describe_signal(signal).to_string(),
+            JobState::Done(Exit::Signal(signal, true)) => format!("{} (core dumped)", describe_signal(signal)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Process {
+    pub pid: i32,
+    pub state: JobState,
+}
+
+#[derive(Debug, Clone)]
 pub struct Job {
+    /// 0 until the job is handed to the BackgroundManager
     pub job_number: usize,
-    pub pid: i32,
+    /// Process group shared by every process of the job
+    pub pgid: i32,
+    /// Every process of the job, in pipeline order
+    pub processes: Vec<Process>,
     pub command: String,
-    pub finished: bool,
+    /// The user has been told about the job's current state
+    pub notified: bool,
+    pub started: Instant,
+    /// When the last process of the job was reaped
+    pub finished: Option<Instant>,
+}
+
+impl Job {
+    /// A job whose processes have all just been started
+    pub fn new(pgid: i32, pids: Vec<i32>, command: String) -> Self {
+        let processes = pids
+            .into_iter()
+            .map(|pid| Process { pid, state: JobState::Running })
+            .collect();
+        Self {
+            job_number: 0,
+            pgid,
+            processes,
+            command,
+            notified: true,
+            started: Instant::now(),
+            finished: None,
+        }
+    }
+
+    /// Done once every process is; otherwise running while any process runs, and
+    /// stopped when the rest are all stopped
+    pub fn state(&self) -> JobState {
+        if self.processes.iter().any(|p| p.state == JobState::Running) {
+            return JobState::Running;
+        }
+        match self.processes.iter().find(|p| matches!(p.state, JobState::Stopped(_))) {
+            Some(stopped) => stopped.state,
+            // A pipeline's status is that of its last command
+            None => self.processes.last().map_or(JobState::Done(Exit::Code(0)), |p| p.state),
+        }
+    }
+
+    /// The exit status of each process, in pipeline order
+    pub fn statuses(&self) -> Vec<i32> {
+        self.processes.iter().map(|p| p.state.status()).collect()
+    }
+
+    /// The processes that have not been reaped yet
+    pub fn live_pids(&self) -> Vec<Pid> {
+        self.processes
+            .iter()
+            .filter(|p| !matches!(p.state, JobState::Done(_)))
+            .map(|p| Pid::from_raw(p.pid))
+            .collect()
+    }
+
+    /// Mark stopped processes as running again once they were sent SIGCONT
+    pub fn continued(&mut self) {
+        for process in self.processes.iter_mut() {
+            if matches!(process.state, JobState::Stopped(_)) {
+                process.state = JobState::Running;
+            }
+        }
+    }
+
+    /// Record a new state for one of the job's processes. Returns false if `pid` is not
+    /// part of the job.
+    pub fn set_state(&mut self, pid: i32, state: JobState) -> bool {
+        let before = self.state();
+        let Some(process) = self.processes.iter_mut().find(|p| p.pid == pid) else {
+            return false;
+        };
+        process.state = state;
+        // Only stopping and finishing are worth telling the user about
+        if self.state() != before && self.state() != JobState::Running {
+            self.notified = false;
+        }
+        if matches!(self.state(), JobState::Done(_)) {
+            self.finished.get_or_insert_with(Instant::now);
+        }
+        true
+    }
+
+    /// Wall-clock time from start until the job finished, or until now
+    pub fn duration(&self) -> Duration {
+        self.finished.unwrap_or_else(Instant::now) - self.started
+    }
+
+    /// Collect any state changes of the job's processes without blocking
+    fn poll(&mut self) {
+        let flags = WaitPidFlag::WNOHANG | WaitPidFlag::WUNTRACED | WaitPidFlag::WCONTINUED;
+        for pid in self.live_pids() {
+            if let Ok(status) = waitpid(pid, Some(flags))
+                && let Some(state) = JobState::from_wait(status)
+            {
+                self.set_state(pid.as_raw(), state);
+            }
+        }
+    }
+}
+
+/// How `jobs` should show and filter the job list
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ListOptions {
+    /// `-l`: also show each process's pid
+    pub long: bool,
+    /// `-p`: only show each job's process group id
+    pub pids_only: bool,
+    /// `-r`: only running jobs
+    pub running: bool,
+    /// `-s`: only stopped jobs
+    pub stopped: bool,
 }
 
 pub struct BackgroundManager {
     jobs: Vec<Job>,
-    next_job_number: usize,
+    /// Job numbers from least to most recently started or resumed; the last is the
+    /// current job (`%+`) and the one before it the previous job (`%-`)
+    recent: Vec<usize>,
+    /// Recently finished jobs, oldest first, so `wait` still knows their status
+    completed: Vec<Job>,
+    /// Last process of the most recent background job, expanded by `$!`
+    pub last_pid: Option<i32>,
 }
 
 impl BackgroundManager {
     pub fn new() -> Self {
         Self {
             jobs: Vec::new(),
-            next_job_number: 1,
+            recent: Vec::new(),
+            completed: Vec::new(),
+            last_pid: None,
         }
     }
 
-    /// Add a new job to the background manager
-    pub fn add_job(&mut self, pid: i32, command: String) {
-        let job = Job {
-            job_number: self.next_job_number,
-            pid,
-            command,
-            finished: false,
-        };
-        println!("[{}] {}", job.job_number, pid);
+    /// Add a new job to the background manager, announcing its last process like bash
+    pub fn add_job(&mut self, pgid: i32, pids: Vec<i32>, command: String) {
+        let mut job = Job::new(pgid, pids, command);
+        job.job_number = self.next_job_number();
+        let last_pid = job.processes.last().map_or(pgid, |p| p.pid);
+        println!("[{}] {}", job.job_number, last_pid);
+        self.last_pid = Some(last_pid);
+        self.recent.push(job.job_number);
         self.jobs.push(job);
-        self.next_job_number += 1;
     }
 
-    /// Periodically check if any background jobs finished
-    pub fn check_and_cleanup_jobs(&mut self) {
-        let mut finished_jobs_to_report = Vec::new();
+    /// Take back a foreground job that was stopped, keeping the number it already had,
+    /// make it the current job and report it
+    pub fn add_stopped_job(&mut self, mut job: Job) {
+        if job.job_number == 0 {
+            job.job_number = self.next_job_number();
+        }
+        // The ^Z echoed by the terminal is still on the line
+        println!();
+        self.make_current(job.job_number);
+        self.print_job(&job, ListOptions::default());
+        job.notified = true;
+        self.insert_job(job);
+    }
+
+    /// The number for a new job: one more than the highest tracked job, so numbers
+    /// of finished jobs are reused as in bash
+    fn next_job_number(&self) -> usize {
+        self.jobs.iter().map(|job| job.job_number).max().unwrap_or(0) + 1
+    }
+
+    /// Put a job back in number order
+    fn insert_job(&mut self, job: Job) {
+        let index = self.jobs.partition_point(|j| j.job_number < job.job_number);
+        self.jobs.insert(index, job);
+    }
 
-        // 1. Mark finished jobs and collect data for reporting
+    /// Reap every job's processes that finished or stopped, without reporting them yet
+    pub fn reap(&mut self) {
         for job in self.jobs.iter_mut() {
-            if !job.finished {
-                // Use Pid::from_raw(job.pid) to convert i32 to nix::unistd::Pid
-                match waitpid(
-                    nix::unistd::Pid::from_raw(job.pid),
-                    Some(WaitPidFlag::WNOHANG),
-                ) {
-                    // Correctly combining Exited and Signaled statuses
-                    Ok(WaitStatus::Exited(_, _) | WaitStatus::Signaled(_, _, _)) => {
-                        job.finished = true;
-                        // Clone the finished job to report it *outside* the mutable iteration loop
-                        finished_jobs_to_report.push(job.clone());
-                    }
-                    _ => {} // Handles StillAlive, Stopped, Continued, and Errors
+            job.poll();
+        }
+    }
+
+    /// Record a state change of `pid` that was collected elsewhere, such as while the
+    /// shell waited for a foreground job. Processes of no tracked job are ignored.
+    pub fn record(&mut self, pid: i32, state: JobState) {
+        for job in self.jobs.iter_mut() {
+            if job.set_state(pid, state) {
+                return;
+            }
+        }
+    }
+
+    /// Report every job that finished or stopped since it was last reported, and stop
+    /// tracking the finished ones. Returns whether anything was printed.
+    pub fn report_changes(&mut self) -> bool {
+        let mut printed = false;
+        for job in self.jobs.iter().filter(|job| !job.notified) {
+            self.print_job(job, ListOptions::default());
+            printed = true;
+        }
+        for job in self.jobs.iter_mut() {
+            job.notified = true;
+        }
+        self.retire_finished();
+        printed
+    }
+
+    /// Handle a SIGCHLD that arrived while the shell waited for input: reap at once
+    /// and, in notify mode (`set -b`), report straight away rather than before the
+    /// next prompt. Returns whether anything was printed over the prompt.
+    pub fn child_changed(&mut self, notify: bool) -> bool {
+        self.reap();
+        if !notify || self.jobs.iter().all(|job| job.notified) {
+            return false;
+        }
+        // Step off the prompt line first
+        println!();
+        self.report_changes()
+    }
+
+    /// Periodically check if any background jobs finished or were stopped
+    pub fn check_and_cleanup_jobs(&mut self) {
+        self.reap();
+        self.report_changes();
+    }
+
+    /// Find the job named by a job spec: `%N`, `%+`/`%%`/`%` for the current job,
+    /// `%-` for the previous one, `%?text` for the job whose command contains text
+    /// and `%text` for the one whose command starts with it
+    pub fn find_job(&self, spec: &str) -> Result<usize, String> {
+        let no_such_job = || format!("{}: no such job", spec);
+        let Some(name) = spec.strip_prefix('%') else {
+            return Err(no_such_job());
+        };
+
+        let number = match name {
+            "" | "+" | "%" => self.recent.last().copied(),
+            "-" => self.recent.iter().rev().nth(1).copied(),
+            _ if name.bytes().all(|b| b.is_ascii_digit()) => name.parse().ok(),
+            _ => {
+                let matching: Vec<&Job> = match name.strip_prefix('?') {
+                    Some(text) => self.jobs.iter().filter(|j| j.command.contains(text)).collect(),
+                    None => self.jobs.iter().filter(|j| j.command.starts_with(name)).collect(),
+                };
+                match matching.as_slice() {
+                    [job] => Some(job.job_number),
+                    [] => None,
+                    _ => return Err(format!("{}: ambiguous job spec", spec)),
                 }
             }
+        };
+        number
+            .filter(|n| self.jobs.iter().any(|j| j.job_number == *n))
+            .ok_or_else(no_such_job)
+    }
+
+    /// The job with the given number
+    pub fn job(&self, job_number: usize) -> Option<&Job> {
+        self.jobs.iter().find(|j| j.job_number == job_number)
+    }
+
+    /// The job with the given number, for updating its state
+    pub fn job_mut(&mut self, job_number: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|j| j.job_number == job_number)
+    }
+
+    /// The state last recorded for the process `pid` of an active job
+    pub fn process_state(&self, pid: i32) -> Option<JobState> {
+        self.jobs
+            .iter()
+            .flat_map(|job| &job.processes)
+            .find(|p| p.pid == pid)
+            .map(|p| p.state)
+    }
+
+    /// The job that owns the process `pid`, if any
+    pub fn job_of_pid(&self, pid: i32) -> Option<usize> {
+        self.jobs
+            .iter()
+            .find(|j| j.processes.iter().any(|p| p.pid == pid))
+            .map(|j| j.job_number)
+    }
+
+    /// Make a job the current job, as when it is continued
+    pub fn make_current(&mut self, job_number: usize) {
+        self.recent.retain(|&n| n != job_number);
+        self.recent.push(job_number);
+    }
+
+    /// Stop tracking a job and hand it back to the caller
+    pub fn remove_job(&mut self, job_number: usize) -> Option<Job> {
+        let index = self.jobs.iter().position(|j| j.job_number == job_number)?;
+        let job = self.jobs.remove(index);
+        self.forget_finished();
+        Some(job)
+    }
+
+    /// Take a job out for the shell to wait on it, keeping its place as current or
+    /// previous job until it is handed back with `return_job`
+    pub fn take_job(&mut self, job_number: usize) -> Option<Job> {
+        let index = self.jobs.iter().position(|j| j.job_number == job_number)?;
+        Some(self.jobs.remove(index))
+    }
+
+    /// Hand back a job taken with `take_job`. A job that is done is dropped, while
+    /// one still running or stopped is tracked again.
+    pub fn return_job(&mut self, job: Job) {
+        if !matches!(job.state(), JobState::Done(_)) {
+            self.insert_job(job);
         }
+        self.forget_finished();
+    }
 
-        // 2. Print completion messages
-        for job in finished_jobs_to_report {
-            println!("[{}] + done {}", job.job_number, job.command);
+    /// Note that `pid` was reaped outside the manager, dropping its job once every
+    /// process of it is gone
+    pub fn forget_pid(&mut self, pid: i32, state: JobState) {
+        self.record(pid, state);
+        if let Some(number) = self.job_of_pid(pid)
+            && self.job(number).is_some_and(|job| matches!(job.state(), JobState::Done(_)))
+        {
+            self.remove_job(number);
         }
+    }
 
-        // 3. Cleanup: Remove all entries that are marked finished
-        self.jobs.retain(|job| !job.finished);
+    /// Stop tracking every job
+    pub fn remove_all(&mut self) {
+        self.jobs.clear();
+        self.recent.clear();
     }
 
-    /// Print the list of active background jobs
-    pub fn list_jobs(&self) {
-        if self.jobs.is_empty() {
+    /// Drop job numbers that no longer belong to a tracked job from `recent`
+    fn forget_finished(&mut self) {
+        let jobs = &self.jobs;
+        self.recent.retain(|n| jobs.iter().any(|j| j.job_number == *n));
+    }
+
+    /// Print the jobs named by `numbers` (every job when empty) that pass the filters
+    /// in `options`. Jobs found to be done are shown one last time and then dropped.
+    pub fn list_jobs(&mut self, numbers: &[usize], options: ListOptions) {
+        self.reap();
+        if self.jobs.is_empty() && numbers.is_empty() && !(options.running || options.stopped) {
             println!("No active background processes.");
             return;
         }
+
+        let mut shown = Vec::new();
         for job in &self.jobs {
-            println!("[{}]+ {} {}", job.job_number, job.pid, job.command);
+            if !numbers.is_empty() && !numbers.contains(&job.job_number) {
+                continue;
+            }
+            let selected = match job.state() {
+                JobState::Running => !options.stopped,
+                JobState::Stopped(_) => !options.running,
+                JobState::Done(_) => !options.running && !options.stopped,
+            };
+            if !selected {
+                continue;
+            }
+            if options.pids_only {
+                println!("{}", job.pgid);
+            } else {
+                self.print_job(job, options);
+            }
+            shown.push(job.job_number);
+        }
+
+        // What was listed needs no separate report
+        for job in self.jobs.iter_mut().filter(|j| shown.contains(&j.job_number)) {
+            job.notified = true;
         }
+        self.retire_finished();
     }
 
-    /// Block until all tracked background jobs finish
-    pub fn wait_all(&mut self) {
-        // Wait for each job's pid to finish; ignore errors
-        for job in &self.jobs {
-            let _ = waitpid(Pid::from_raw(job.pid), None);
+    /// Move finished jobs the user has been told about to `completed`
+    fn retire_finished(&mut self) {
+        let (retired, active): (Vec<Job>, Vec<Job>) = self
+            .jobs
+            .drain(..)
+            .partition(|job| job.notified && matches!(job.state(), JobState::Done(_)));
+        self.jobs = active;
+        self.completed.extend(retired);
+        let excess = self.completed.len().saturating_sub(COMPLETED_LIMIT);
+        self.completed.drain(..excess);
+        self.forget_finished();
+    }
+
+    /// Take the final state of a finished job that has already been reported, given
+    /// its job number or the pid of one of its processes, so it can be waited for once
+    pub fn take_completed(&mut self, job_number: Option<usize>, pid: Option<i32>) -> Option<JobState> {
+        let index = self.completed.iter().position(|job| {
+            Some(job.job_number) == job_number || job.processes.iter().any(|p| Some(p.pid) == pid)
+        })?;
+        let job = self.completed.remove(index);
+        match pid {
+            Some(pid) => job.processes.iter().find(|p| p.pid == pid).map(|p| p.state),
+            None => Some(job.state()),
         }
-        // After waiting, clear the list
-        self.jobs.clear();
+    }
+
+    /// Print one line for a job in the style of bash's `jobs`
+    fn print_job(&self, job: &Job, options: ListOptions) {
+        let marker = if self.recent.last() == Some(&job.job_number) {
+            '+'
+        } else if self.recent.iter().rev().nth(1) == Some(&job.job_number) {
+            '-'
+        } else {
+            ' '
+        };
+        let state = job.state().describe();
+        let suffix = match job.state() {
+            JobState::Running => " &".to_string(),
+            JobState::Done(_) => format!("  ({:.2}s)", job.duration().as_secs_f64()),
+            JobState::Stopped(_) => String::new(),
+        };
+
+        if options.long {
+            // One line per process, with the command only on the first
+            for (i, process) in job.processes.iter().enumerate() {
+                if i == 0 {
+                    println!("[{}]{} {:>7} {:<24}{}{}", job.job_number, marker, process.pid, state, job.command, suffix);
+                } else {
+                    println!("     {:>7}", process.pid);
+                }
+            }
+        } else {
+            println!("[{}]{}  {:<24}{}{}", job.job_number, marker, state, job.command, suffix);
+        }
+    }
+
+    /// Block until every running background job has finished or stopped. Finished
+    /// jobs are dropped; stopped ones stay tracked, as waiting for them would never end.
+    /// Returns false if SIGINT cut the wait short, leaving the rest tracked.
+    pub fn wait_all(&mut self) -> bool {
+        while let Some(pid) = self.running_pid() {
+            let state = match waitpid(Pid::from_raw(pid), Some(WaitPidFlag::WUNTRACED)) {
+                Ok(status) => JobState::from_wait(status),
+                Err(Errno::EINTR) if take_interrupt() => return false,
+                Err(Errno::EINTR) => continue,
+                // Reaped elsewhere, so it cannot still be running
+                Err(_) => Some(JobState::Done(Exit::Code(1))),
+            };
+            if let Some(state) = state {
+                self.record(pid, state);
+            }
+        }
+        self.jobs.retain(|job| !matches!(job.state(), JobState::Done(_)));
+        self.forget_finished();
+        true
+    }
+
+    /// A process of some job that is still running, if any
+    fn running_pid(&self) -> Option<i32> {
+        self.jobs
+            .iter()
+            .flat_map(|job| &job.processes)
+            .find(|p| p.state == JobState::Running)
+            .map(|p| p.pid)
+    }
+
+    /// Send SIGHUP to every stopped job, and SIGCONT so it acts on it, as the shell is
+    /// leaving and nothing could resume them afterwards
+    pub fn hang_up_stopped(&self, job_control: bool) {
+        for job in self.jobs.iter().filter(|job| matches!(job.state(), JobState::Stopped(_))) {
+            let pgid = Pid::from_raw(job.pgid);
+            let pids = job.live_pids();
+            let _ = signal_job(pgid, &pids, Signal::SIGHUP, job_control);
+            let _ = signal_job(pgid, &pids, Signal::SIGCONT, job_control);
+        }
+    }
+
+    /// True if any job is stopped
+    pub fn has_stopped_jobs(&self) -> bool {
+        self.jobs.iter().any(|job| matches!(job.state(), JobState::Stopped(_)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A manager tracking one single-process job per command, numbered from 1
+    fn manager(commands: &[&str]) -> BackgroundManager {
+        let mut manager = BackgroundManager::new();
+        for (i, command) in commands.iter().enumerate() {
+            let pid = 1000 + i as i32;
+            manager.add_job(pid, vec![pid], command.to_string());
+        }
+        manager
+    }
+
+    #[test]
+    fn job_numbers_follow_the_highest_tracked_job() {
+        let mut manager = manager(&["a", "b", "c"]);
+        manager.remove_job(3);
+        manager.remove_job(1);
+        manager.add_job(2000, vec![2000], "d".to_string());
+        assert_eq!(manager.find_job("%d"), Ok(3));
+
+        manager.remove_job(2);
+        manager.remove_job(3);
+        manager.add_job(2001, vec![2001], "e".to_string());
+        assert_eq!(manager.find_job("%e"), Ok(1));
+    }
+
+    #[test]
+    fn find_job_by_number() {
+        let manager = manager(&["sleep 10", "cat"]);
+        assert_eq!(manager.find_job("%2"), Ok(2));
+        assert_eq!(manager.find_job("%3"), Err("%3: no such job".to_string()));
+        assert_eq!(manager.find_job("2"), Err("2: no such job".to_string()));
+    }
+
+    #[test]
+    fn find_current_and_previous_job() {
+        let mut manager = manager(&["a", "b", "c"]);
+        for spec in ["%", "%+", "%%"] {
+            assert_eq!(manager.find_job(spec), Ok(3), "{}", spec);
+        }
+        assert_eq!(manager.find_job("%-"), Ok(2));
+
+        manager.make_current(1);
+        assert_eq!(manager.find_job("%+"), Ok(1));
+        assert_eq!(manager.find_job("%-"), Ok(3));
+    }
+
+    #[test]
+    fn current_job_moves_on_when_it_is_removed() {
+        let mut manager = manager(&["a", "b"]);
+        manager.remove_job(2);
+        assert_eq!(manager.find_job("%+"), Ok(1));
+        assert_eq!(manager.find_job("%-"), Err("%-: no such job".to_string()));
+    }
+
+    #[test]
+    fn find_job_by_command_text() {
+        let manager = manager(&["sleep 10", "vim notes", "sleep 20"]);
+        assert_eq!(manager.find_job("%vim"), Ok(2));
+        assert_eq!(manager.find_job("%?notes"), Ok(2));
+        assert_eq!(manager.find_job("%?20"), Ok(3));
+        assert_eq!(manager.find_job("%sleep"), Err("%sleep: ambiguous job spec".to_string()));
+        assert_eq!(manager.find_job("%?x"), Err("%?x: no such job".to_string()));
+        assert_eq!(manager.find_job("%notes"), Err("%notes: no such job".to_string()));
+    }
+
+    #[test]
+    fn describe_how_a_job_ended() {
+        assert_eq!(JobState::Done(Exit::Code(0)).describe(), "Done");
+        assert_eq!(JobState::Done(Exit::Code(2)).describe(), "Exit 2");
+        assert_eq!(JobState::Done(Exit::Signal(Signal::SIGKILL, false)).describe(), "Killed");
+        assert_eq!(JobState::Done(Exit::Signal(Signal::SIGTERM, false)).describe(), "Terminated");
+        assert_eq!(
+            JobState::Done(Exit::Signal(Signal::SIGSEGV, true)).describe(),
+            "Segmentation fault (core dumped)"
+        );
+    }
+
+    #[test]
+    fn describe_running_and_stopped_jobs() {
+        assert_eq!(JobState::Running.describe(), "Running");
+        assert_eq!(JobState::Stopped(Signal::SIGTSTP).describe(), "Stopped");
+        assert_eq!(JobState::Stopped(Signal::SIGSTOP).describe(), "Stopped (signal)");
+        assert_eq!(JobState::Stopped(Signal::SIGTTIN).describe(), "Stopped (tty input)");
+        assert_eq!(JobState::Stopped(Signal::SIGTTOU).describe(), "Stopped (tty output)");
+    }
+
+    #[test]
+    fn job_status_follows_its_last_process() {
+        assert_eq!(JobState::Done(Exit::Code(3)).status(), 3);
+        assert_eq!(JobState::Done(Exit::Signal(Signal::SIGINT, false)).status(), 130);
+        assert_eq!(JobState::Stopped(Signal::SIGTSTP).status(), 148);
+
+        let mut job = Job::new(10, vec![10, 11], "a | b".to_string());
+        job.set_state(11, JobState::Done(Exit::Code(1)));
+        assert_eq!(job.state(), JobState::Running);
+        job.set_state(10, JobState::Done(Exit::Code(0)));
+        assert_eq!(job.state(), JobState::Done(Exit::Code(1)));
+        assert_eq!(job.statuses(), vec![0, 1]);
     }
 }
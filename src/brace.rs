@@ -0,0 +1,271 @@
+use crate::lexer::{Quote, Word, WordPart};
+
+/// A character of a word together with its quoting; None marks an empty quoted
+/// part such as `""`, which must survive so the word still exists
+type Item = (Option<char>, Quote);
+
+/// Expand unquoted `{a,b,c}` alternatives and `{x..y[..step]}` sequences, including
+/// nested braces. Braces that are unbalanced or hold neither form are left untouched.
+pub fn expand_braces(word: &Word) -> Vec<Word> {
+    let has_brace = word
+        .parts
+        .iter()
+        .any(|p| p.quote == Quote::None && p.text.contains('{'));
+    if !has_brace {
+        return vec![word.clone()];
+    }
+
+    let mut items: Vec<Item> = Vec::new();
+    for part in &word.parts {
+        if part.text.is_empty() {
+            items.push((None, part.quote));
+        }
+        items.extend(part.text.chars().map(|c| (Some(c), part.quote)));
+    }
+
+    expand_items(&items).iter().map(|items| to_word(items)).collect()
+}
+
+fn expand_items(items: &[Item]) -> Vec<Vec<Item>> {
+    let mut i = 0;
+    while i < items.len() {
+        match items[i] {
+            // Leave ${...} and $(...) to the later expansion stages
+            (Some('$'), Quote::None)
+                if matches!(items.get(i + 1), Some((Some('{' | '('), Quote::None))) =>
+            {
+                i = skip_dollar(items, i + 1);
+                continue;
+            }
+            (Some('{'), Quote::None) => {
+                if let Some(close) = matching_close(items, i)
+                    && let Some(alternatives) = alternatives(&items[i + 1..close])
+                {
+                    let mut results = Vec::new();
+                    for alternative in alternatives {
+                        let mut expanded = items[..i].to_vec();
+                        expanded.extend(alternative);
+                        expanded.extend_from_slice(&items[close + 1..]);
+                        results.extend(expand_items(&expanded));
+                    }
+                    return results;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    vec![items.to_vec()]
+}
+
+/// Index just past the `${...}` or `$(...)` whose opening bracket is at `open`
+fn skip_dollar(items: &[Item], open: usize) -> usize {
+    let (open_c, close_c) = match items[open].0 {
+        Some('(') => ('(', ')'),
+        _ => ('{', '}'),
+    };
+    let mut depth = 0;
+    for (i, item) in items.iter().enumerate().skip(open) {
+        match item {
+            (Some(c), Quote::None) if *c == open_c => depth += 1,
+            (Some(c), Quote::None) if *c == close_c => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    items.len()
+}
+
+/// Index of the unquoted '}' matching the '{' at `open`
+fn matching_close(items: &[Item], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, item) in items.iter().enumerate().skip(open) {
+        match item {
+            (Some('{'), Quote::None) => depth += 1,
+            (Some('}'), Quote::None) => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The alternatives described by the inside of a brace pair, or None if it is
+/// neither a comma list nor a valid sequence
+fn alternatives(inner: &[Item]) -> Option<Vec<Vec<Item>>> {
+    // Split on top-level unquoted commas
+    let mut pieces: Vec<Vec<Item>> = vec![Vec::new()];
+    let mut depth = 0;
+    for &item in inner {
+        match item {
+            (Some('{'), Quote::None) => depth += 1,
+            (Some('}'), Quote::None) => depth -= 1,
+            (Some(','), Quote::None) if depth == 0 => {
+                pieces.push(Vec::new());
+                continue;
+            }
+            _ => {}
+        }
+        if let Some(last) = pieces.last_mut() {
+            last.push(item);
+        }
+    }
+    if pieces.len() > 1 {
+        return Some(pieces);
+    }
+
+    if inner.iter().any(|(_, quote)| *quote != Quote::None) {
+        return None;
+    }
+    let text: String = inner.iter().filter_map(|(c, _)| *c).collect();
+    let sequence = sequence(&text)?;
+    Some(
+        sequence
+            .iter()
+            .map(|s| s.chars().map(|c| (Some(c), Quote::None)).collect())
+            .collect(),
+    )
+}
+
+/// Expand `x..y` or `x..y..step` where x and y are both integers or both single
+/// characters. Integers written with leading zeros are zero-padded to equal width.
+fn sequence(text: &str) -> Option<Vec<String>> {
+    let fields: Vec<&str> = text.split("..").collect();
+    let (start, end, step) = match fields.as_slice() {
+        [start, end] => (*start, *end, 1),
+        [start, end, step] => (*start, *end, step.parse::<i64>().ok()?.unsigned_abs().max(1)),
+        _ => return None,
+    };
+
+    if let (Ok(a), Ok(b)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let padded = |s: &str| s.trim_start_matches('-').len() > 1 && s.trim_start_matches('-').starts_with('0');
+        let width = if padded(start) || padded(end) { start.len().max(end.len()) } else { 0 };
+        return Some(
+            range(a, b, step)
+                .map(|n| {
+                    if n < 0 {
+                        format!("-{:0>w$}", -n, w = width.saturating_sub(1))
+                    } else {
+                        format!("{:0>w$}", n, w = width)
+                    }
+                })
+                .collect(),
+        );
+    }
+
+    let mut a = start.chars();
+    let mut b = end.chars();
+    match (a.next(), a.next(), b.next(), b.next()) {
+        (Some(a), None, Some(b), None) if a.is_ascii_alphabetic() && b.is_ascii_alphabetic() => Some(
+            range(a as i64, b as i64, step)
+                .filter_map(|n| char::from_u32(n as u32))
+                .map(|c| c.to_string())
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// Inclusive range from `a` to `b` in either direction
+fn range(a: i64, b: i64, step: u64) -> impl Iterator<Item = i64> {
+    let count = a.abs_diff(b) / step + 1;
+    let step = if a <= b { step as i64 } else { -(step as i64) };
+    (0..count as i64).map(move |i| a + i * step)
+}
+
+/// Rebuild a word from characters, merging neighbours with the same quoting
+fn to_word(items: &[Item]) -> Word {
+    let mut word = Word::default();
+    for &(c, quote) in items {
+        match word.parts.last_mut() {
+            Some(part) if part.quote == quote => part.text.extend(c),
+            _ => word.parts.push(WordPart {
+                text: c.map(String::from).unwrap_or_default(),
+                quote,
+                expanded: false,
+            }),
+        }
+    }
+    word
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(text: &str) -> Word {
+        Word {
+            parts: vec![WordPart {
+                text: text.to_string(),
+                quote: Quote::None,
+                expanded: false,
+            }],
+        }
+    }
+
+    fn expand(text: &str) -> Vec<String> {
+        expand_braces(&plain(text)).iter().map(Word::text).collect()
+    }
+
+    #[test]
+    fn comma_lists_with_prefix_and_suffix() {
+        assert_eq!(expand("a{b,c,d}e"), vec!["abe", "ace", "ade"]);
+        assert_eq!(expand("{,x}y"), vec!["y", "xy"]);
+    }
+
+    #[test]
+    fn nested_and_adjacent_braces() {
+        assert_eq!(expand("{a,b{1,2}}"), vec!["a", "b1", "b2"]);
+        assert_eq!(expand("{a,b}{1,2}"), vec!["a1", "a2", "b1", "b2"]);
+    }
+
+    #[test]
+    fn numeric_sequences_count_both_ways_with_a_step() {
+        assert_eq!(expand("{1..4}"), vec!["1", "2", "3", "4"]);
+        assert_eq!(expand("{3..1}"), vec!["3", "2", "1"]);
+        assert_eq!(expand("{0..10..5}"), vec!["0", "5", "10"]);
+        assert_eq!(expand("{-1..1}"), vec!["-1", "0", "1"]);
+    }
+
+    #[test]
+    fn leading_zeros_pad_to_equal_width() {
+        assert_eq!(expand("{01..03}"), vec!["01", "02", "03"]);
+        assert_eq!(expand("{8..010}"), vec!["008", "009", "010"]);
+        assert_eq!(expand("{-01..1}"), vec!["-01", "000", "001"]);
+    }
+
+    #[test]
+    fn letter_sequences() {
+        assert_eq!(expand("{a..e..2}"), vec!["a", "c", "e"]);
+        assert_eq!(expand("{C..A}"), vec!["C", "B", "A"]);
+    }
+
+    #[test]
+    fn invalid_forms_are_left_alone() {
+        assert_eq!(expand("{a}"), vec!["{a}"]);
+        assert_eq!(expand("{a,b"), vec!["{a,b"]);
+        assert_eq!(expand("{1..b}"), vec!["{1..b}"]);
+        assert_eq!(expand("{ab..c}"), vec!["{ab..c}"]);
+        assert_eq!(expand("${x}"), vec!["${x}"]);
+    }
+
+    #[test]
+    fn quoted_braces_do_not_expand() {
+        let word = Word {
+            parts: vec![WordPart {
+                text: "{a,b}".to_string(),
+                quote: Quote::Single,
+                expanded: false,
+            }],
+        };
+        assert_eq!(expand_braces(&word), vec![word]);
+    }
+}
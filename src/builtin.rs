@@ -1,39 +1,103 @@
+use nix::errno::Errno;
+use nix::sys::signal::{Signal, kill};
+use nix::sys::stat::{Mode, umask};
+use nix::sys::wait::{WaitPidFlag, waitpid};
+use nix::unistd::Pid;
 use std::env;
+use std::io::{self, Write};
+use std::process;
 use std::path::Path;
-use crate::background::BackgroundManager;
+use crate::background::{BackgroundManager, Exit, JobState, ListOptions};
+use crate::environment::Variables;
+use crate::job_control::{give_terminal_to, run_foreground, signal_job, wait_for_job};
+use crate::parser::is_valid_name;
+use crate::shell::{Options, Shell};
+use crate::signals::{parse_signal, signal_name, take_interrupt};
+use crate::trap::{Condition, Traps, run_exit_trap};
+
+/// Names handled by `execute_builtin`
+const BUILTINS: &[&str] = &[
+    "exit", "cd", "jobs", "export", "unset", "readonly", "shopt", "set", "umask", "fg", "bg",
+    "kill", "wait", "disown", "trap",
+];
+
+/// True if `command` names a built-in command
+pub fn is_builtin(command: &str) -> bool {
+    BUILTINS.contains(&command)
+}
 
 /// Execute built-in commands
-/// Returns Ok(true) if shell should exit, Ok(false) if command was a builtin and executed
+/// Returns Ok(status) with the builtin's exit status once it has run;
+/// `exit` additionally sets `shell.exit_requested`
 /// Returns Err if the command is not a builtin
-pub fn execute_builtin(
-    command: &str,
-    args: &[String],
-    history: &[String],
-    bg_manager: &mut BackgroundManager,
-) -> Result<bool, String> {
+pub fn execute_builtin(command: &str, args: &[String], shell: &mut Shell) -> Result<i32, String> {
     match command {
-        "exit" => {
-            execute_exit(history, bg_manager)?;
-            Ok(true) // Signal to exit the shell
-        }
+        "exit" => Ok(report_status(execute_exit(args, shell), 1)),
         "cd" => {
-            if let Err(e) = execute_cd(args) {
+            if let Err(e) = execute_cd(args, shell.vars.get("HOME")) {
                 eprintln!("{}", e);
+                return Ok(1);
             }
-            Ok(false) // Continue shell execution
-        }
-        "jobs" => {
-            execute_jobs(bg_manager)?;
-            Ok(false) // Continue shell execution
+            Ok(0) // Continue shell execution
         }
-        _ => Err(format!("not a builtin")), // Not a built-in command
+        "jobs" => Ok(report(execute_jobs(args, &mut shell.bg_manager))),
+        "export" => Ok(report(execute_export(args, &mut shell.vars))),
+        "unset" => Ok(report(execute_unset(args, &mut shell.vars))),
+        "readonly" => Ok(report(execute_readonly(args, &mut shell.vars))),
+        "shopt" => Ok(report(execute_shopt(args, &mut shell.options))),
+        "set" => Ok(report(execute_set(args, &mut shell.options))),
+        "umask" => Ok(report(execute_umask(args))),
+        "fg" => Ok(report_status(execute_fg(args, shell), 1)),
+        "bg" => Ok(report(execute_bg(args, shell))),
+        "kill" => Ok(report_status(execute_kill(args, shell), 1)),
+        "wait" => Ok(report_status(execute_wait(args, shell), 127)),
+        "disown" => Ok(report(execute_disown(args, &mut shell.bg_manager))),
+        "trap" => Ok(execute_trap(args, &mut shell.traps)),
+        _ => Err("not a builtin".to_string()), // Not a built-in command
     }
 }
 
-/// Execute the exit command
-fn execute_exit(history: &[String], bg_manager: &mut BackgroundManager) -> Result<(), String> {
+/// Execute the exit command: `exit [N]`, where N defaults to the status of the last
+/// command. Returns N, which the shell exits with, and sets `shell.exit_requested`
+/// unless the first attempt is held back by stopped jobs or Ctrl-C interrupts the
+/// wait for background jobs.
+fn execute_exit(args: &[String], shell: &mut Shell) -> Result<i32, String> {
+    let status = match args {
+        [] => shell.last_status,
+        [code] => match code.parse::<i64>() {
+            Ok(code) => code.rem_euclid(256) as i32,
+            Err(_) => {
+                eprintln!("exit: {}: numeric argument required", code);
+                2
+            }
+        },
+        _ => return Err("exit: too many arguments".to_string()),
+    };
+
+    // A subshell's jobs and history belong to the parent; it only has to end
+    if shell.subshell {
+        run_exit_trap(shell);
+        let _ = io::stdout().flush();
+        process::exit(status);
+    }
+
+    if !shell.may_leave() {
+        return Ok(1);
+    }
+
     // Wait for any background processes to finish
-    bg_manager.wait_all();
+    if !shell.bg_manager.wait_all() {
+        return Ok(interrupted());
+    }
+
+    run_exit_trap(shell);
+    if shell.exit_requested {
+        // The EXIT trap ran `exit` itself, and its status stands
+        return Ok(shell.last_status);
+    }
+    shell.exit_requested = true; // Signal to exit the shell
+
+    let history = &shell.history;
 
     // Display the last three valid commands
     let n = history.len();
@@ -48,14 +112,14 @@ fn execute_exit(history: &[String], bg_manager: &mut BackgroundManager) -> Resul
     }
 
     println!("Exiting shell.");
-    Ok(())
+    Ok(status)
 }
 
 /// Execute the cd command
-fn execute_cd(args: &[String]) -> Result<(), String> {
+fn execute_cd(args: &[String], home: Option<&str>) -> Result<(), String> {
     let target_path = if args.is_empty() {
         // If no arguments, change to $HOME
-        env::var("HOME").map_err(|_| "HOME environment variable not set".to_string())?
+        home.ok_or_else(|| "cd: HOME not set".to_string())?.to_string()
     } else if args.len() > 1 {
         return Err("cd: too many arguments".to_string());
     } else {
@@ -79,8 +143,636 @@ fn execute_cd(args: &[String]) -> Result<(), String> {
     Ok(())
 }
 
-/// Execute the jobs command
-fn execute_jobs(bg_manager: &BackgroundManager) -> Result<(), String> {
-    bg_manager.list_jobs();
+/// Execute the jobs command: `jobs [-lprs] [%job...]`
+fn execute_jobs(args: &[String], bg_manager: &mut BackgroundManager) -> Result<(), String> {
+    let mut options = ListOptions::default();
+    let mut numbers = Vec::new();
+    for arg in args {
+        match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        'l' => options.long = true,
+                        'p' => options.pids_only = true,
+                        'r' => options.running = true,
+                        's' => options.stopped = true,
+                        _ => return Err(format!("jobs: -{}: invalid option", flag)),
+                    }
+                }
+            }
+            _ => numbers.push(bg_manager.find_job(arg).map_err(|e| format!("jobs: {}", e))?),
+        }
+    }
+    bg_manager.list_jobs(&numbers, options);
     Ok(())
 }
+
+/// Print a builtin's error and turn the result into an exit status
+fn report(result: Result<(), String>) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+/// Like `report`, for builtins that produce their own exit status; an error gives `failure`
+fn report_status(result: Result<i32, String>, failure: i32) -> i32 {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        failure
+    })
+}
+
+/// Resolve the job spec argument of `fg`, `bg` or `disown`, defaulting to the current job
+fn job_argument(builtin: &str, args: &[String], bg_manager: &BackgroundManager) -> Result<usize, String> {
+    match args.first() {
+        Some(spec) => bg_manager.find_job(spec),
+        None => bg_manager.find_job("%+").map_err(|_| "current: no such job".to_string()),
+    }
+    .map_err(|e| format!("{}: {}", builtin, e))
+}
+
+/// Execute the fg command: `fg [%job]`, continuing the job with the terminal in its hands
+fn execute_fg(args: &[String], shell: &mut Shell) -> Result<i32, String> {
+    if !shell.job_control {
+        return Err("fg: no job control".to_string());
+    }
+    let number = job_argument("fg", args, &shell.bg_manager)?;
+    let mut job = shell.bg_manager.remove_job(number).ok_or("fg: no such job")?;
+    println!("{}", job.command);
+
+    let pgid = Pid::from_raw(job.pgid);
+    // Hand over the terminal first, so the job can use it as soon as it runs again
+    give_terminal_to(pgid);
+    let _ = signal_job(pgid, &job.live_pids(), Signal::SIGCONT, true);
+    job.continued();
+    let statuses = run_foreground(job, shell);
+    Ok(statuses.last().copied().unwrap_or(0))
+}
+
+/// Execute the bg command: `bg [%job]`, continuing a stopped job in the background
+fn execute_bg(args: &[String], shell: &mut Shell) -> Result<(), String> {
+    if !shell.job_control {
+        return Err("bg: no job control".to_string());
+    }
+    let number = job_argument("bg", args, &shell.bg_manager)?;
+    let job = shell.bg_manager.job_mut(number).ok_or("bg: no such job")?;
+    if job.state() == JobState::Running {
+        eprintln!("bg: job {} already in background", number);
+        return Ok(());
+    }
+
+    signal_job(Pid::from_raw(job.pgid), &job.live_pids(), Signal::SIGCONT, true)
+        .map_err(|e| format!("bg: {}", e))?;
+    job.continued();
+    let command = job.command.clone();
+    shell.bg_manager.make_current(number);
+    println!("[{}]+ {} &", number, command);
+    Ok(())
+}
+
+/// Execute the kill command: `kill [-s SIG | -n NUM | -SIG] pid|%job...` or `kill -l [N]`
+fn execute_kill(args: &[String], shell: &Shell) -> Result<i32, String> {
+    let invalid = |spec: &str| format!("kill: {}: invalid signal specification", spec);
+
+    let mut signal = Signal::SIGTERM;
+    let mut targets = args;
+    match args.first().map(|a| a.as_str()) {
+        Some("-l" | "-L") => {
+            if args.len() == 1 {
+                let names: Vec<&str> = Signal::iterator().map(signal_name).collect();
+                println!("{}", names.join(" "));
+            }
+            for arg in &args[1..] {
+                // An exit status from a signal death names that signal
+                let signal = match arg.parse::<i32>() {
+                    Ok(n) => Signal::try_from(if n > 128 { n - 128 } else { n }).ok(),
+                    Err(_) => parse_signal(arg),
+                };
+                match signal {
+                    Some(signal) if arg.parse::<i32>().is_ok() => println!("{}", signal_name(signal)),
+                    Some(signal) => println!("{}", signal as i32),
+                    None => return Err(invalid(arg)),
+                }
+            }
+            return Ok(0);
+        }
+        Some("-s" | "-n") => {
+            let spec = args.get(1).ok_or("kill: option requires an argument")?;
+            signal = parse_signal(spec).ok_or_else(|| invalid(spec))?;
+            targets = &args[2..];
+        }
+        Some("--") => targets = &args[1..],
+        Some(arg) if arg.starts_with('-') && arg.len() > 1 => {
+            signal = parse_signal(&arg[1..]).ok_or_else(|| invalid(&arg[1..]))?;
+            targets = &args[1..];
+        }
+        _ => {}
+    }
+    if targets.is_empty() {
+        return Err("kill: usage: kill [-s sigspec | -n signum | -sigspec] pid | jobspec ... or kill -l [sigspec]".to_string());
+    }
+
+    let mut status = 0;
+    for target in targets {
+        let result = if target.starts_with('%') {
+            shell.bg_manager.find_job(target).and_then(|number| {
+                let job = shell.bg_manager.job(number).ok_or(format!("{}: no such job", target))?;
+                let (pgid, pids) = (Pid::from_raw(job.pgid), job.live_pids());
+                signal_job(pgid, &pids, signal, shell.job_control).map_err(|e| format!("{}: {}", target, e.desc()))?;
+                // A stopped job only acts on the signal once it runs again
+                if matches!(job.state(), JobState::Stopped(_)) && signal != Signal::SIGCONT {
+                    let _ = signal_job(pgid, &pids, Signal::SIGCONT, shell.job_control);
+                }
+                Ok(())
+            })
+        } else {
+            match target.parse::<i32>() {
+                Ok(pid) => kill(Pid::from_raw(pid), signal).map_err(|e| format!("({}) - {}", pid, e.desc())),
+                Err(_) => Err(format!("{}: arguments must be process or job IDs", target)),
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("kill: {}", e);
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+/// Execute the wait command: `wait [%job|pid...]`. With no arguments waits for every
+/// job and returns 0; otherwise returns the status of the last job or process named.
+fn execute_wait(args: &[String], shell: &mut Shell) -> Result<i32, String> {
+    if args.is_empty() {
+        return Ok(if shell.bg_manager.wait_all() { 0 } else { interrupted() });
+    }
+
+    let mut status = 0;
+    for arg in args {
+        if let Some(name) = arg.strip_prefix('%') {
+            let state = match shell.bg_manager.find_job(arg) {
+                Ok(number) => {
+                    let mut job = shell.bg_manager.take_job(number).ok_or(format!("wait: {}: no such job", arg))?;
+                    // Return as soon as it stops too, or a stopped job would be waited on forever
+                    let finished = wait_for_job(&mut job, true, shell);
+                    let state = job.state();
+                    shell.bg_manager.return_job(job);
+                    if !finished {
+                        return Ok(interrupted());
+                    }
+                    state
+                }
+                // A job that already finished and was reported can still be waited for once
+                Err(e) => {
+                    let number = name.parse().ok();
+                    shell.bg_manager.take_completed(number, None).ok_or(format!("wait: {}", e))?
+                }
+            };
+            status = state.status();
+        } else {
+            let pid: i32 = arg
+                .parse()
+                .map_err(|_| format!("wait: `{}': not a pid or valid job spec", arg))?;
+            let state = match shell.bg_manager.process_state(pid) {
+                // Reaped already but its job not yet reported, or stopped
+                Some(state @ (JobState::Done(_) | JobState::Stopped(_))) => state,
+                Some(JobState::Running) => loop {
+                    match waitpid(Pid::from_raw(pid), Some(WaitPidFlag::WUNTRACED)) {
+                        Err(Errno::EINTR) if take_interrupt() => return Ok(interrupted()),
+                        Err(Errno::EINTR) => continue,
+                        result => {
+                            break result.ok().and_then(JobState::from_wait).unwrap_or(JobState::Done(Exit::Code(127)));
+                        }
+                    }
+                },
+                None => shell
+                    .bg_manager
+                    .take_completed(None, Some(pid))
+                    .ok_or(format!("wait: pid {} is not a child of this shell", pid))?,
+            };
+            shell.bg_manager.forget_pid(pid, state);
+            status = state.status();
+        }
+    }
+    Ok(status)
+}
+
+/// The status of a wait cut short by Ctrl-C, once the line the ^C was echoed on is ended
+fn interrupted() -> i32 {
+    println!();
+    128 + Signal::SIGINT as i32
+}
+
+/// Execute the disown command: `disown [-a] [%job...]`, forgetting the jobs so they
+/// are neither reported nor waited for
+fn execute_disown(args: &[String], bg_manager: &mut BackgroundManager) -> Result<(), String> {
+    if args.first().is_some_and(|a| a == "-a") {
+        bg_manager.remove_all();
+        return Ok(());
+    }
+    if args.is_empty() {
+        let number = job_argument("disown", args, bg_manager)?;
+        bg_manager.remove_job(number);
+    }
+    for spec in args {
+        let number = bg_manager.find_job(spec).map_err(|e| format!("disown: {}", e))?;
+        bg_manager.remove_job(number);
+    }
+    Ok(())
+}
+
+/// Execute the trap command: `trap [-p] [[ACTION] CONDITION...]`. ACTION `-` resets
+/// each condition and an empty ACTION ignores the signal. With no ACTION, or an
+/// ACTION that is a number, every operand is a condition to reset.
+fn execute_trap(args: &[String], traps: &mut Traps) -> i32 {
+    let mut args = args;
+    if args.first().is_some_and(|a| a == "--") {
+        args = &args[1..];
+    }
+    if args.len() <= 1 && args.first().is_none_or(|a| a == "-p") {
+        for condition in traps.conditions() {
+            traps.print(condition);
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    if args[0] == "-p" {
+        for spec in &args[1..] {
+            match Condition::parse(spec) {
+                Some(condition) => traps.print(condition),
+                None => {
+                    eprintln!("trap: {}", invalid_condition(spec));
+                    status = 1;
+                }
+            }
+        }
+        return status;
+    }
+
+    let (action, conditions) = if args.len() == 1 || args[0].parse::<u32>().is_ok() {
+        ("-", args)
+    } else {
+        (args[0].as_str(), &args[1..])
+    };
+    for spec in conditions {
+        let result = match Condition::parse(spec) {
+            Some(condition) if action == "-" => {
+                traps.reset(condition);
+                Ok(())
+            }
+            Some(condition) => traps.set(condition, action),
+            None => Err(invalid_condition(spec)),
+        };
+        if let Err(e) = result {
+            eprintln!("trap: {}", e);
+            status = 1;
+        }
+    }
+    status
+}
+
+/// The error for a `trap` condition that names no signal
+fn invalid_condition(spec: &str) -> String {
+    format!("{}: invalid signal specification", spec)
+}
+
+/// Split a `NAME[=value]` argument, checking that the name is valid
+fn split_name_value<'a>(builtin: &str, arg: &'a str) -> Result<(&'a str, Option<&'a str>), String> {
+    let (name, value) = match arg.split_once('=') {
+        Some((name, value)) => (name, Some(value)),
+        None => (arg, None),
+    };
+    if !is_valid_name(name) {
+        return Err(format!("{}: `{}': not a valid identifier", builtin, arg));
+    }
+    Ok((name, value))
+}
+
+/// Execute the export command: `export [-p] [NAME[=value]...]`
+fn execute_export(args: &[String], vars: &mut Variables) -> Result<(), String> {
+    if args.is_empty() || args.iter().all(|a| a == "-p") {
+        for (name, var) in vars.sorted() {
+            if var.exported {
+                print_variable("export", name, var.value.as_deref());
+            }
+        }
+        return Ok(());
+    }
+
+    for arg in args.iter().filter(|a| *a != "-p") {
+        let (name, value) = split_name_value("export", arg)?;
+        vars.export(name, value)?;
+    }
+    Ok(())
+}
+
+/// Execute the readonly command: `readonly [-p] [NAME[=value]...]`
+fn execute_readonly(args: &[String], vars: &mut Variables) -> Result<(), String> {
+    if args.is_empty() || args.iter().all(|a| a == "-p") {
+        for (name, var) in vars.sorted() {
+            if var.readonly {
+                print_variable("readonly", name, var.value.as_deref());
+            }
+        }
+        return Ok(());
+    }
+
+    for arg in args.iter().filter(|a| *a != "-p") {
+        let (name, value) = split_name_value("readonly", arg)?;
+        vars.set_readonly(name, value)?;
+    }
+    Ok(())
+}
+
+/// Execute the unset command: `unset [-v] NAME...`
+fn execute_unset(args: &[String], vars: &mut Variables) -> Result<(), String> {
+    for arg in args.iter().filter(|a| *a != "-v") {
+        if !is_valid_name(arg) {
+            return Err(format!("unset: `{}': not a valid identifier", arg));
+        }
+        vars.unset(arg)?;
+    }
+    Ok(())
+}
+
+/// Print a variable in a form that can be read back by the shell
+fn print_variable(keyword: &str, name: &str, value: Option<&str>) {
+    match value {
+        Some(value) => {
+            let escaped = value.replace('\\', "\\\\").replace('"', "\\\"").replace('$', "\\$");
+            println!("{} {}=\"{}\"", keyword, name, escaped);
+        }
+        None => println!("{} {}", keyword, name),
+    }
+}
+
+/// Execute the shopt command: `shopt [-s|-u] [OPTNAME...]`
+fn execute_shopt(args: &[String], options: &mut Options) -> Result<(), String> {
+    let (setting, names) = match args.first().map(|a| a.as_str()) {
+        Some("-s") => (Some(true), &args[1..]),
+        Some("-u") => (Some(false), &args[1..]),
+        _ => (None, args),
+    };
+
+    let all = ["nullglob", "failglob", "globstar"];
+    let names: Vec<&str> = if names.is_empty() {
+        all.to_vec()
+    } else {
+        names.iter().map(|n| n.as_str()).collect()
+    };
+
+    for name in names {
+        let option = match name {
+            "nullglob" => &mut options.nullglob,
+            "failglob" => &mut options.failglob,
+            "globstar" => &mut options.globstar,
+            _ => return Err(format!("shopt: {}: invalid shell option name", name)),
+        };
+        match setting {
+            Some(value) => *option = value,
+            None => println!("{:<15} {}", name, if *option { "on" } else { "off" }),
+        }
+    }
+    Ok(())
+}
+
+/// Names accepted by `set -o`, in the order they are listed
+const SET_OPTIONS: &[&str] = &["noclobber", "notify", "pipefail"];
+
+fn set_option<'a>(options: &'a mut Options, name: &str) -> Option<&'a mut bool> {
+    match name {
+        "noclobber" => Some(&mut options.noclobber),
+        "notify" => Some(&mut options.notify),
+        "pipefail" => Some(&mut options.pipefail),
+        _ => None,
+    }
+}
+
+/// Execute the set command: `set [-o|+o] [OPTION]`, with `-C`/`+C` short for noclobber
+/// and `-b`/`+b` for notify
+fn execute_set(args: &[String], options: &mut Options) -> Result<(), String> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let enable = arg.starts_with('-');
+        let name = match arg.as_str() {
+            "-o" | "+o" => args.next().map(|name| name.as_str()),
+            "-C" | "+C" => Some("noclobber"),
+            "-b" | "+b" => Some("notify"),
+            _ => return Err(format!("set: {}: invalid option", arg)),
+        };
+
+        let Some(name) = name else {
+            // `set -o` lists the options, `set +o` prints them as commands
+            for &name in SET_OPTIONS {
+                let on = set_option(options, name).is_some_and(|on| *on);
+                if enable {
+                    println!("{:<15} {}", name, if on { "on" } else { "off" });
+                } else {
+                    println!("set {}o {}", if on { "-" } else { "+" }, name);
+                }
+            }
+            return Ok(());
+        };
+        match set_option(options, name) {
+            Some(option) => *option = enable,
+            None => return Err(format!("set: {}: invalid option name", name)),
+        }
+    }
+    Ok(())
+}
+
+/// Execute the umask command: `umask [-p] [-S] [MODE]`, where MODE is octal or
+/// symbolic like `u=rwx,g-w,o=`
+fn execute_umask(args: &[String]) -> Result<(), String> {
+    let mut symbolic = false;
+    let mut reusable = false;
+    let mut mode = None;
+    for arg in args {
+        match arg.as_str() {
+            "-S" => symbolic = true,
+            "-p" => reusable = true,
+            a if a.starts_with('-') && a.len() > 1 => return Err(format!("umask: {}: invalid option", a)),
+            a => mode = Some(a),
+        }
+    }
+
+    // umask(2) can only be read by setting it, so put the old value straight back
+    let old = umask(Mode::empty());
+    umask(old);
+    let current = old.bits() & 0o777;
+
+    let Some(mode) = mode else {
+        let shown = if symbolic { symbolic_mode(current) } else { format!("{:04o}", current) };
+        if reusable {
+            println!("umask {}{}", if symbolic { "-S " } else { "" }, shown);
+        } else {
+            println!("{}", shown);
+        }
+        return Ok(());
+    };
+
+    let mask = if mode.starts_with(|c: char| c.is_ascii_digit()) {
+        match u32::from_str_radix(mode, 8) {
+            Ok(mask) if mask <= 0o777 => mask,
+            _ => return Err(format!("umask: {}: octal number out of range", mode)),
+        }
+    } else {
+        parse_symbolic_umask(mode, current)?
+    };
+    umask(Mode::from_bits_truncate(mask as _));
+    Ok(())
+}
+
+/// Show a mask as the permissions it leaves, e.g. 022 as `u=rwx,g=rx,o=rx`
+fn symbolic_mode(mask: u32) -> String {
+    let allowed = !mask & 0o777;
+    let class = |shift: u32| -> String {
+        let bits = (allowed >> shift) & 0o7;
+        [(0o4, 'r'), (0o2, 'w'), (0o1, 'x')]
+            .iter()
+            .filter(|(bit, _)| bits & bit != 0)
+            .map(|(_, c)| *c)
+            .collect()
+    };
+    format!("u={},g={},o={}", class(6), class(3), class(0))
+}
+
+/// Apply symbolic clauses such as `u=rwx,go-w` to the permissions `mask` allows and
+/// return the resulting mask
+fn parse_symbolic_umask(mode: &str, mask: u32) -> Result<u32, String> {
+    let mut allowed = !mask & 0o777;
+    for clause in mode.split(',') {
+        let mut chars = clause.chars().peekable();
+
+        let mut who = 0;
+        while let Some(&c) = chars.peek() {
+            who |= match c {
+                'u' => 0o700,
+                'g' => 0o070,
+                'o' => 0o007,
+                'a' => 0o777,
+                _ => break,
+            };
+            chars.next();
+        }
+        if who == 0 {
+            who = 0o777;
+        }
+
+        let mut any_op = false;
+        while let Some(op) = chars.next() {
+            if !matches!(op, '+' | '-' | '=') {
+                return Err(format!("umask: `{}': invalid symbolic mode operator", op));
+            }
+            any_op = true;
+
+            let mut perms = 0;
+            while let Some(&c) = chars.peek() {
+                perms |= match c {
+                    'r' => 0o444,
+                    'w' => 0o222,
+                    'x' => 0o111,
+                    '+' | '-' | '=' => break,
+                    _ => return Err(format!("umask: `{}': invalid symbolic mode character", c)),
+                };
+                chars.next();
+            }
+            let perms = perms & who;
+            match op {
+                '+' => allowed |= perms,
+                '-' => allowed &= !perms,
+                _ => allowed = (allowed & !who) | perms,
+            }
+        }
+        if !any_op {
+            return Err(format!("umask: `{}': invalid symbolic mode operator", clause));
+        }
+    }
+    Ok(!allowed & 0o777)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbolic_umask_adds_removes_and_sets() {
+        assert_eq!(parse_symbolic_umask("g-w", 0o022), Ok(0o022));
+        assert_eq!(parse_symbolic_umask("go+w", 0o022), Ok(0o000));
+        assert_eq!(parse_symbolic_umask("o-rwx", 0o022), Ok(0o027));
+        assert_eq!(parse_symbolic_umask("u=rwx,g=rx,o=", 0o000), Ok(0o027));
+        assert_eq!(parse_symbolic_umask("a=r", 0o022), Ok(0o333));
+    }
+
+    #[test]
+    fn symbolic_umask_without_who_means_all() {
+        assert_eq!(parse_symbolic_umask("-x", 0o022), Ok(0o133));
+        assert_eq!(parse_symbolic_umask("=rw", 0o077), Ok(0o111));
+    }
+
+    #[test]
+    fn symbolic_umask_chains_operators_in_a_clause() {
+        assert_eq!(parse_symbolic_umask("u=rw+x-w", 0o000), Ok(0o200));
+    }
+
+    #[test]
+    fn symbolic_umask_rejects_bad_clauses() {
+        assert!(parse_symbolic_umask("u", 0o022).is_err());
+        assert!(parse_symbolic_umask("u*r", 0o022).is_err());
+        assert!(parse_symbolic_umask("u+z", 0o022).is_err());
+        assert!(parse_symbolic_umask("u+r,", 0o022).is_err());
+    }
+
+    fn trap(args: &[&str], traps: &mut Traps) -> i32 {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        execute_trap(&args, traps)
+    }
+
+    #[test]
+    fn trap_sets_a_command_on_every_condition_given() {
+        let mut traps = Traps::default();
+        assert_eq!(trap(&["echo bye", "EXIT", "ERR"], &mut traps), 0);
+        assert_eq!(traps.command(Condition::Exit), Some("echo bye"));
+        assert_eq!(traps.command(Condition::Err), Some("echo bye"));
+
+        assert_eq!(trap(&["--", "-x", "EXIT"], &mut traps), 0);
+        assert_eq!(traps.command(Condition::Exit), Some("-x"));
+    }
+
+    #[test]
+    fn trap_resets_with_a_dash_a_lone_condition_or_a_number_first() {
+        let mut traps = Traps::default();
+        trap(&["x", "EXIT", "ERR"], &mut traps);
+        assert_eq!(trap(&["-", "EXIT"], &mut traps), 0);
+        assert_eq!(traps.command(Condition::Exit), None);
+        assert_eq!(trap(&["ERR"], &mut traps), 0);
+        assert_eq!(traps.command(Condition::Err), None);
+
+        trap(&["x", "EXIT", "ERR"], &mut traps);
+        assert_eq!(trap(&["0", "ERR"], &mut traps), 0);
+        assert!(traps.conditions().is_empty());
+    }
+
+    #[test]
+    fn trap_reports_invalid_conditions_but_sets_the_valid_ones() {
+        let mut traps = Traps::default();
+        assert_eq!(trap(&["x", "BOGUS", "EXIT"], &mut traps), 1);
+        assert_eq!(traps.command(Condition::Exit), Some("x"));
+        assert_eq!(trap(&["-p", "EXIT", "BOGUS"], &mut traps), 1);
+        assert_eq!(trap(&["-p"], &mut traps), 0);
+        assert_eq!(trap(&[], &mut traps), 0);
+    }
+
+    #[test]
+    fn trap_with_an_empty_command_ignores_a_signal() {
+        let mut traps = Traps::default();
+        assert_eq!(trap(&["", "USR2"], &mut traps), 0);
+        assert_eq!(traps.command(Condition::Signal(Signal::SIGUSR2)), Some(""));
+        assert_eq!(trap(&["-", "USR2"], &mut traps), 0);
+        assert_eq!(traps.command(Condition::Signal(Signal::SIGUSR2)), None);
+    }
+}
@@ -1,14 +1,647 @@
+use std::collections::HashMap;
 use std::env;
+use std::ffi::CString;
 
-pub fn get_env(token: &str) -> String {
-    if token.starts_with('$') {
-        let var_name = &token[1..];
+use crate::executor::execute_list;
+use crate::lexer::{Quote, Word, WordPart, get_tokens, parse_word};
+use crate::parser::{is_valid_name, parse};
+use crate::piping::capture_output;
+use crate::pattern::{matches, word_to_pattern};
+use crate::shell::Shell;
 
-            // Get environment variable value
-        env::var(var_name).unwrap_or_else(|_| String::from("$"))
+/// A shell variable; exported ones are passed to child processes
+#[derive(Debug, Clone, Default)]
+pub struct Variable {
+    /// None for a name that was exported or made readonly before being given a value
+    pub value: Option<String>,
+    pub exported: bool,
+    pub readonly: bool,
+}
+
+/// The shell's variable table, seeded from the process environment at startup
+#[derive(Debug, Clone, Default)]
+pub struct Variables {
+    vars: HashMap<String, Variable>,
+}
+
+impl Variables {
+    /// Import every environment variable as an exported shell variable
+    pub fn from_env() -> Self {
+        let mut vars = HashMap::new();
+        for (name, value) in env::vars_os() {
+            if let (Ok(name), Ok(value)) = (name.into_string(), value.into_string()) {
+                let var = Variable {
+                    value: Some(value),
+                    exported: true,
+                    readonly: false,
+                };
+                vars.insert(name, var);
+            }
+        }
+        Self { vars }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).and_then(|v| v.value.as_deref())
+    }
+
+    /// Set a variable, keeping its exported/readonly flags
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let var = self.vars.entry(name.to_string()).or_default();
+        if var.readonly {
+            return Err(format!("{}: readonly variable", name));
+        }
+        var.value = Some(value.to_string());
+        Ok(())
+    }
+
+    /// Mark a variable for export, optionally assigning it first
+    pub fn export(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        if let Some(value) = value {
+            self.set(name, value)?;
+        }
+        self.vars.entry(name.to_string()).or_default().exported = true;
+        Ok(())
+    }
+
+    /// Mark a variable readonly, optionally assigning it first
+    pub fn set_readonly(&mut self, name: &str, value: Option<&str>) -> Result<(), String> {
+        if let Some(value) = value {
+            self.set(name, value)?;
+        }
+        self.vars.entry(name.to_string()).or_default().readonly = true;
+        Ok(())
+    }
+
+    pub fn unset(&mut self, name: &str) -> Result<(), String> {
+        match self.vars.get(name) {
+            Some(var) if var.readonly => Err(format!("{}: cannot unset: readonly variable", name)),
+            _ => {
+                self.vars.remove(name);
+                Ok(())
+            }
+        }
+    }
+
+    /// All variables sorted by name, for `export -p` and `readonly -p`
+    pub fn sorted(&self) -> Vec<(&String, &Variable)> {
+        let mut vars: Vec<_> = self.vars.iter().collect();
+        vars.sort_by(|a, b| a.0.cmp(b.0));
+        vars
+    }
+
+    /// Build a child's environment: every exported variable, then the per-command
+    /// `NAME=value` prefix assignments, which take precedence
+    pub fn environ(&self, overrides: &[(String, String)]) -> Vec<CString> {
+        let mut env: Vec<(&str, &str)> = self
+            .vars
+            .iter()
+            .filter(|(name, _)| !overrides.iter().any(|(o, _)| o == *name))
+            .filter_map(|(name, var)| match (&var.value, var.exported) {
+                (Some(value), true) => Some((name.as_str(), value.as_str())),
+                _ => None,
+            })
+            .collect();
+        env.extend(overrides.iter().map(|(n, v)| (n.as_str(), v.as_str())));
+
+        env.iter()
+            .filter_map(|(name, value)| CString::new(format!("{}={}", name, value)).ok())
+            .collect()
+    }
+}
+
+/// Expand `$VAR`, `${...}`, special parameters and `$(...)`/`` `...` `` command
+/// substitutions anywhere in the parts of the word that were not single-quoted.
+/// Text produced by an unquoted expansion is marked for field splitting.
+/// Fails for `${VAR:?msg}` and malformed expansions.
+pub fn get_env(word: &Word, shell: &mut Shell) -> Result<Word, String> {
+    let mut out = Word::default();
+    for part in &word.parts {
+        if part.quote == Quote::Single || !part.text.contains(['$', '`']) {
+            out.parts.push(part.clone());
+            continue;
+        }
+        for piece in expand_text(&part.text, shell)? {
+            out.parts.push(WordPart {
+                text: piece.text,
+                // Quotes inside a `${VAR:-word}` operand still protect their text
+                quote: if piece.quote == Quote::None { part.quote } else { piece.quote },
+                expanded: piece.expanded && part.quote == Quote::None,
+            });
+        }
+    }
+    Ok(out)
+}
+
+/// Expand every `$` and backquote form inside one piece of text. Returns the
+/// literal text and the expansion results as parts, the latter marked `expanded`.
+fn expand_text(text: &str, shell: &mut Shell) -> Result<Vec<WordPart>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut segments: Vec<WordPart> = Vec::new();
+    let mut literal = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let expansion = match (chars[i], chars.get(i + 1)) {
+            ('$', Some('{')) => {
+                let end = matching(&chars, i + 1, '{', '}')
+                    .ok_or_else(|| "unexpected EOF while looking for matching `}'".to_string())?;
+                let inner: String = chars[i + 2..end].iter().collect();
+                i = end + 1;
+                expand_braced(&inner, shell)?
+            }
+            ('$', Some('(')) => {
+                let end = matching(&chars, i + 1, '(', ')')
+                    .ok_or_else(|| "unexpected EOF while looking for matching `)'".to_string())?;
+                let inner: String = chars[i + 2..end].iter().collect();
+                i = end + 1;
+                vec![expanded_part(command_substitution(&inner, shell)?)]
+            }
+            ('$', Some(&c)) if c == '?' || c == '$' || c == '!' || c.is_ascii_digit() => {
+                i += 2;
+                vec![expanded_part(lookup(&c.to_string(), shell).unwrap_or_default())]
+            }
+            ('$', Some(&c)) if c.is_ascii_alphabetic() || c == '_' => {
+                let start = i + 1;
+                let mut end = start;
+                while end < chars.len() && (chars[end].is_ascii_alphanumeric() || chars[end] == '_') {
+                    end += 1;
+                }
+                let name: String = chars[start..end].iter().collect();
+                i = end;
+                vec![expanded_part(lookup(&name, shell).unwrap_or_default())]
+            }
+            ('`', _) => {
+                // Inside backquotes a backslash only escapes $ ` and \
+                let mut inner = String::new();
+                let mut j = i + 1;
+                while j < chars.len() && chars[j] != '`' {
+                    if chars[j] == '\\' && matches!(chars.get(j + 1), Some('$' | '`' | '\\')) {
+                        j += 1;
+                    }
+                    inner.push(chars[j]);
+                    j += 1;
+                }
+                i = j + 1;
+                vec![expanded_part(command_substitution(&inner, shell)?)]
+            }
+            // A lone '$' stays literal
+            (c, _) => {
+                literal.push(c);
+                i += 1;
+                continue;
+            }
+        };
+
+        if !literal.is_empty() {
+            segments.push(literal_part(std::mem::take(&mut literal)));
+        }
+        segments.extend(expansion);
+    }
+
+    if !literal.is_empty() || segments.is_empty() {
+        segments.push(literal_part(literal));
+    }
+    Ok(segments)
+}
+
+/// Index of the `close` matching the `open` at index `start`, skipping quoted text
+/// and nested pairs
+fn matching(chars: &[char], start: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    let mut in_single = false;
+    let mut in_double = false;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if !in_single => i += 1,
+            '\'' if !in_double => in_single = !in_single,
+            '"' if !in_single => in_double = !in_double,
+            c if c == open && !in_single && !in_double => depth += 1,
+            c if c == close && !in_single && !in_double => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Run `command` in a child shell and return its output minus trailing newlines.
+/// `$?` is set to the command's exit status.
+fn command_substitution(command: &str, shell: &mut Shell) -> Result<String, String> {
+    let tokens = get_tokens(command)?;
+    let list = parse(&tokens.items)?;
+
+    let (output, status) = capture_output(|| {
+        // The substitution's commands belong to the job that is expanding it
+        shell.job_control = false;
+        shell.enter_subshell();
+        execute_list(&list, shell);
+        shell.last_status
+    })
+    .map_err(|e| e.to_string())?;
+
+    shell.last_status = status;
+    shell.substitution_status = Some(status);
+    Ok(output.trim_end_matches('\n').to_string())
+}
+
+/// Split a word into fields at the IFS characters found in unquoted expansion results.
+/// A word that expands to nothing unquoted yields no fields at all.
+pub fn split_fields(word: &Word, ifs: &str) -> Vec<Word> {
+    let mut fields = Vec::new();
+    let mut current = Word::default();
+    // Whether `current` holds anything that must be kept, even if it is empty ("")
+    let mut has_content = false;
+
+    for part in &word.parts {
+        if !part.expanded {
+            has_content |= part.quote != Quote::None || !part.text.is_empty();
+            current.parts.push(part.clone());
+            continue;
+        }
+
+        let mut piece = String::new();
+        for c in part.text.chars() {
+            if !ifs.contains(c) {
+                piece.push(c);
+                continue;
+            }
+            if !piece.is_empty() {
+                current.parts.push(expanded_part(std::mem::take(&mut piece)));
+                has_content = true;
+            }
+            // IFS whitespace only ends a non-empty field; other IFS characters always do
+            if has_content || !c.is_whitespace() {
+                fields.push(std::mem::take(&mut current));
+                has_content = false;
+            }
+        }
+        if !piece.is_empty() {
+            current.parts.push(expanded_part(piece));
+            has_content = true;
+        }
+    }
+
+    if has_content {
+        fields.push(current);
+    }
+    fields
+}
+
+fn expanded_part(text: String) -> WordPart {
+    WordPart {
+        text,
+        quote: Quote::None,
+        expanded: true,
+    }
+}
+
+fn literal_part(text: String) -> WordPart {
+    WordPart {
+        text,
+        quote: Quote::None,
+        expanded: false,
     }
-    else {
-    token.to_string()
+}
+
+/// Look up a variable or special parameter (`$?`, `$$`, `$PIPESTATUS`)
+fn lookup(name: &str, shell: &Shell) -> Option<String> {
+    match name {
+        "?" => Some(shell.last_status.to_string()),
+        "$" => Some(shell.pid.to_string()),
+        "!" => shell.bg_manager.last_pid.map(|pid| pid.to_string()),
+        // Without a subscript an array stands for its first element
+        "PIPESTATUS" => shell.pipestatus.first().map(|s| s.to_string()),
+        _ => shell.vars.get(name).map(|v| v.to_string()),
+    }
+}
+
+/// Expand `PIPESTATUS[sub]`, the only array the shell has. `@` and `*` give
+/// every element; `length` asks for the element count or the element's length.
+fn array_subscript(name: &str, rest: &str, length: bool, shell: &Shell) -> Option<String> {
+    let subscript = rest.strip_prefix('[')?.strip_suffix(']')?;
+    if name != "PIPESTATUS" {
+        return None;
+    }
+    let elements: Vec<String> = shell.pipestatus.iter().map(|s| s.to_string()).collect();
+
+    if subscript == "@" || subscript == "*" {
+        return Some(if length { elements.len().to_string() } else { elements.join(" ") });
     }
+    let element = elements.get(subscript.parse::<usize>().ok()?).cloned().unwrap_or_default();
+    Some(if length { element.chars().count().to_string() } else { element })
 }
 
+/// Expand the inside of `${...}`. The text an operand like the `word` of
+/// `${VAR:-word}` supplies keeps the quoting it had inside the braces.
+fn expand_braced(inner: &str, shell: &mut Shell) -> Result<Vec<WordPart>, String> {
+    let bad = || format!("${{{}}}: bad substitution", inner);
+    let value_of = |text: String| vec![expanded_part(text)];
+
+    // ${#VAR}: length of the value in characters
+    if let Some(name) = inner.strip_prefix('#')
+        && !name.is_empty()
+    {
+        if let Some((array, rest)) = name.split_once('[') {
+            return array_subscript(array, &format!("[{}", rest), true, shell).map(value_of).ok_or_else(bad);
+        }
+        if !is_parameter_name(name) {
+            return Err(bad());
+        }
+        return Ok(value_of(lookup(name, shell).unwrap_or_default().chars().count().to_string()));
+    }
+
+    let name_len = if inner.starts_with(['?', '$']) {
+        1
+    } else {
+        inner
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(inner.len())
+    };
+    let (name, rest) = inner.split_at(name_len);
+    if !is_parameter_name(name) {
+        return Err(bad());
+    }
+
+    if rest.starts_with('[') {
+        return array_subscript(name, rest, false, shell).map(value_of).ok_or_else(bad);
+    }
+
+    let value = lookup(name, shell);
+    if rest.is_empty() {
+        return Ok(value_of(value.unwrap_or_default()));
+    }
+
+    // A leading ':' makes the operators treat an empty value like an unset one
+    let (colon, rest) = match rest.strip_prefix(':') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let mut op_chars = rest.chars();
+    let op = op_chars.next().ok_or_else(bad)?;
+    let operand = op_chars.as_str();
+    let is_set = match &value {
+        Some(v) => !(colon && v.is_empty()),
+        None => false,
+    };
+
+    match op {
+        '-' => match value {
+            Some(v) if is_set => Ok(value_of(v)),
+            _ => expand_operand(operand, shell).map(operand_parts),
+        },
+        '=' => match value {
+            Some(v) if is_set => Ok(value_of(v)),
+            _ => {
+                if !is_valid_name(name) {
+                    return Err(format!("${}: cannot assign in this way", name));
+                }
+                let default = expand_operand(operand, shell)?.text();
+                shell.vars.set(name, &default)?;
+                Ok(value_of(default))
+            }
+        },
+        '?' => match value {
+            Some(v) if is_set => Ok(value_of(v)),
+            _ => {
+                let message = expand_operand(operand, shell)?.text();
+                if message.is_empty() {
+                    Err(format!("{}: parameter null or not set", name))
+                } else {
+                    Err(format!("{}: {}", name, message))
+                }
+            }
+        },
+        '+' => {
+            if is_set {
+                expand_operand(operand, shell).map(operand_parts)
+            } else {
+                Ok(value_of(String::new()))
+            }
+        }
+        '#' | '%' if !colon => {
+            let value = value.unwrap_or_default();
+            let longest = operand.starts_with(op);
+            let operand = if longest { &operand[1..] } else { operand };
+            let pattern = word_to_pattern(&expand_operand(operand, shell)?);
+            Ok(value_of(if op == '#' {
+                remove_prefix(&value, &pattern, longest)
+            } else {
+                remove_suffix(&value, &pattern, longest)
+            }))
+        }
+        '/' if !colon => {
+            let value = value.unwrap_or_default();
+            let (mode, operand) = match operand.chars().next() {
+                Some(c @ ('/' | '#' | '%')) => (Some(c), &operand[1..]),
+                _ => (None, operand),
+            };
+            let (pattern, replacement) = split_substitution(operand);
+            let pattern = word_to_pattern(&expand_operand(pattern, shell)?);
+            let replacement = expand_operand(replacement, shell)?.text();
+            Ok(value_of(substitute(&value, &pattern, &replacement, mode)))
+        }
+        _ => Err(bad()),
+    }
+}
+
+/// True for names that can appear inside `${...}`: identifiers, digits, `?` and `$`
+fn is_parameter_name(name: &str) -> bool {
+    is_valid_name(name)
+        || name == "?"
+        || name == "$"
+        || (!name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Remove quotes from an operator's word and expand the parameters inside it
+fn expand_operand(operand: &str, shell: &mut Shell) -> Result<Word, String> {
+    get_env(&parse_word(operand)?, shell)
+}
+
+/// The parts an operand's word contributes to the expansion: what was unquoted is
+/// split into fields like any expansion result, what was quoted is left whole
+fn operand_parts(word: Word) -> Vec<WordPart> {
+    word.parts
+        .into_iter()
+        .map(|part| WordPart {
+            expanded: part.quote == Quote::None,
+            ..part
+        })
+        .collect()
+}
+
+/// Split `pattern/replacement` at the first unquoted, unescaped '/'
+fn split_substitution(operand: &str) -> (&str, &str) {
+    let mut in_single = false;
+    let mut in_double = false;
+    let mut escaped = false;
+    for (i, c) in operand.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if !in_single => escaped = true,
+            '\'' if !in_double => in_single = !in_single,
+            '"' if !in_single => in_double = !in_double,
+            '/' if !in_single && !in_double => return (&operand[..i], &operand[i + 1..]),
+            _ => {}
+        }
+    }
+    (operand, "")
+}
+
+/// Byte offsets of every character boundary in `s`, including the end
+fn boundaries(s: &str) -> Vec<usize> {
+    s.char_indices().map(|(i, _)| i).chain([s.len()]).collect()
+}
+
+/// `${VAR#pat}` / `${VAR##pat}`: drop the shortest or longest matching prefix
+fn remove_prefix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut ends = boundaries(value);
+    if longest {
+        ends.reverse();
+    }
+    for end in ends {
+        if matches(pattern, &value[..end]) {
+            return value[end..].to_string();
+        }
+    }
+    value.to_string()
+}
+
+/// `${VAR%pat}` / `${VAR%%pat}`: drop the shortest or longest matching suffix
+fn remove_suffix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut starts = boundaries(value);
+    if !longest {
+        starts.reverse();
+    }
+    for start in starts {
+        if matches(pattern, &value[start..]) {
+            return value[..start].to_string();
+        }
+    }
+    value.to_string()
+}
+
+/// `${VAR/pat/rep}` and friends. `mode` is '/' to replace every match, '#' or '%'
+/// to anchor the match at the start or end, or None to replace the first match.
+fn substitute(value: &str, pattern: &str, replacement: &str, mode: Option<char>) -> String {
+    if pattern.is_empty() {
+        return value.to_string();
+    }
+    let bounds = boundaries(value);
+
+    match mode {
+        Some('#') => {
+            for &end in bounds.iter().rev() {
+                if matches(pattern, &value[..end]) {
+                    return format!("{}{}", replacement, &value[end..]);
+                }
+            }
+            value.to_string()
+        }
+        Some('%') => {
+            for &start in &bounds {
+                if matches(pattern, &value[start..]) {
+                    return format!("{}{}", &value[..start], replacement);
+                }
+            }
+            value.to_string()
+        }
+        _ => {
+            let all = mode == Some('/');
+            let mut out = String::new();
+            let mut i = 0;
+            while i < bounds.len() - 1 {
+                let start = bounds[i];
+                // Longest non-empty match starting here
+                let found = bounds[i + 1..]
+                    .iter()
+                    .rposition(|&end| matches(pattern, &value[start..end]))
+                    .map(|j| i + 1 + j);
+                match found {
+                    Some(j) => {
+                        out.push_str(replacement);
+                        i = j;
+                        if !all {
+                            out.push_str(&value[bounds[i]..]);
+                            return out;
+                        }
+                    }
+                    None => {
+                        out.push_str(&value[start..bounds[i + 1]]);
+                        i += 1;
+                    }
+                }
+            }
+            out
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IFS: &str = " \t\n";
+
+    fn fields(word: &Word, ifs: &str) -> Vec<String> {
+        split_fields(word, ifs).iter().map(Word::text).collect()
+    }
+
+    fn quoted(text: &str) -> WordPart {
+        WordPart {
+            text: text.to_string(),
+            quote: Quote::Double,
+            expanded: false,
+        }
+    }
+
+    #[test]
+    fn expanded_text_splits_on_ifs_whitespace() {
+        let word = Word { parts: vec![expanded_part("  a b\t\nc  ".to_string())] };
+        assert_eq!(fields(&word, IFS), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn literal_and_quoted_text_is_not_split() {
+        let word = Word { parts: vec![literal_part("a b".to_string()), quoted(" c d ")] };
+        assert_eq!(fields(&word, IFS), vec!["a b c d "]);
+    }
+
+    #[test]
+    fn splits_join_onto_neighbouring_parts() {
+        let word = Word {
+            parts: vec![
+                literal_part("x".to_string()),
+                expanded_part("1 2".to_string()),
+                quoted("y z"),
+            ],
+        };
+        assert_eq!(fields(&word, IFS), vec!["x1", "2y z"]);
+    }
+
+    #[test]
+    fn empty_expansion_leaves_no_field_unless_quoted() {
+        let word = Word { parts: vec![expanded_part("   ".to_string())] };
+        assert!(fields(&word, IFS).is_empty());
+        let word = Word { parts: vec![quoted(""), expanded_part("".to_string())] };
+        assert_eq!(fields(&word, IFS), vec![""]);
+    }
+
+    #[test]
+    fn non_whitespace_ifs_delimits_empty_fields() {
+        let word = Word { parts: vec![expanded_part("a::b:".to_string())] };
+        assert_eq!(fields(&word, ":"), vec!["a", "", "b"]);
+    }
+
+    #[test]
+    fn empty_ifs_does_not_split() {
+        let word = Word { parts: vec![expanded_part("a b".to_string())] };
+        assert_eq!(fields(&word, ""), vec!["a b"]);
+    }
+}
@@ -0,0 +1,315 @@
+use nix::unistd::{ForkResult, fork, getpid};
+use std::ffi::CString;
+use std::io;
+use std::process;
+
+use crate::brace::expand_braces;
+use crate::builtin::{execute_builtin, is_builtin};
+use crate::environment::{get_env, split_fields};
+use crate::glob::{glob, has_pattern_chars};
+use crate::job_control::place_in_job;
+use crate::lexer::{Operator, Word};
+use crate::parser::{AndOrList, CommandList, Connector, Pipeline, SimpleCommand};
+use crate::path_search::search_path;
+use crate::pattern::word_to_pattern;
+use crate::piping::execute_pipeline;
+use crate::redirection::{Redirection, execute_command, redirect_io, resolve_redirect};
+use crate::shell::Shell;
+use crate::signals::reset_child_signals;
+use crate::tilde::expand_tilde;
+use crate::trap::{run_err_trap, run_pending_traps};
+
+/// Execute every item of a command list in order.
+/// Errors are reported as they happen; returns false if any item failed to run.
+pub fn execute_list(list: &CommandList, shell: &mut Shell) -> bool {
+    let mut all_ok = true;
+
+    for item in &list.items {
+        if item.background && !item.and_or.rest.is_empty() {
+            // A trailing '&' applies to the whole and/or list, so run it in a child shell
+            if let Err(err) = spawn_background_list(&item.and_or, shell) {
+                eprintln!("{}", err);
+                all_ok = false;
+            }
+        } else {
+            execute_and_or(&item.and_or, item.background, shell, &mut all_ok);
+        }
+        run_pending_traps(shell);
+        if shell.exit_requested {
+            break;
+        }
+    }
+
+    all_ok
+}
+
+/// Run pipelines joined by `&&`/`||`, skipping each one whose connector
+/// does not match the previous status. Returns the status of the last pipeline run.
+/// The ERR trap goes off if that status is a failure of the list's final pipeline;
+/// a failure that only decides whether the next pipeline runs does not count.
+fn execute_and_or(and_or: &AndOrList, background: bool, shell: &mut Shell, all_ok: &mut bool) -> i32 {
+    let mut status = run_pipeline(&and_or.first, background, shell, all_ok);
+    shell.last_status = status;
+    let mut ran_last = and_or.rest.is_empty();
+
+    for (i, (connector, pipeline)) in and_or.rest.iter().enumerate() {
+        if shell.exit_requested {
+            break;
+        }
+        let run = match connector {
+            Connector::And => status == 0,
+            Connector::Or => status != 0,
+        };
+        if run {
+            status = run_pipeline(pipeline, background, shell, all_ok);
+            shell.last_status = status;
+            ran_last = i + 1 == and_or.rest.len();
+        }
+    }
+
+    if status != 0 && ran_last && !shell.exit_requested {
+        run_err_trap(shell);
+    }
+    status
+}
+
+/// Fork a child that runs the and/or list in the foreground, and track it as a job.
+/// The child leads the job's process group and its commands stay in that group.
+fn spawn_background_list(and_or: &AndOrList, shell: &mut Shell) -> io::Result<()> {
+    match unsafe { fork() }.map_err(io::Error::other)? {
+        ForkResult::Child => {
+            reset_child_signals();
+            shell.enter_subshell();
+            if shell.job_control {
+                place_in_job(getpid(), None, false);
+                shell.job_control = false;
+            }
+            let mut all_ok = true;
+            let status = execute_and_or(and_or, false, shell, &mut all_ok);
+            process::exit(status);
+        }
+        ForkResult::Parent { child } => {
+            if shell.job_control {
+                place_in_job(child, None, false);
+            }
+            shell.bg_manager.add_job(child.as_raw(), vec![child.as_raw()], and_or.to_string());
+            shell.last_status = 0;
+            Ok(())
+        }
+    }
+}
+
+/// Execute a pipeline, reporting any error and turning it into a failure status
+/// (127 when the command was not found, 1 otherwise). Records every stage's status
+/// in PIPESTATUS and returns the last one, or with pipefail the rightmost failure.
+fn run_pipeline(pipeline: &Pipeline, background: bool, shell: &mut Shell, all_ok: &mut bool) -> i32 {
+    let mut statuses = match execute_pipeline_node(pipeline, background, shell) {
+        Ok(statuses) => statuses,
+        Err(err) => {
+            eprintln!("{}", err);
+            *all_ok = false;
+            vec![if err.kind() == io::ErrorKind::NotFound { 127 } else { 1 }]
+        }
+    };
+    if statuses.is_empty() {
+        // A job started in the background counts as success
+        statuses.push(0);
+    }
+
+    let status = if shell.options.pipefail {
+        statuses.iter().rev().find(|&&s| s != 0).copied().unwrap_or(0)
+    } else {
+        statuses.last().copied().unwrap_or(0)
+    };
+    shell.pipestatus = statuses;
+    status
+}
+
+fn execute_pipeline_node(pipeline: &Pipeline, background: bool, shell: &mut Shell) -> io::Result<Vec<i32>> {
+    if pipeline.commands.len() == 1 {
+        let status = execute_simple(&pipeline.commands[0], background, shell)?;
+        return Ok(if background { Vec::new() } else { vec![status] });
+    }
+
+    let mut argvs: Vec<Vec<String>> = Vec::new();
+    let mut envs: Vec<Vec<CString>> = Vec::new();
+    let mut redirects: Vec<Vec<Redirection>> = Vec::new();
+    for cmd in &pipeline.commands {
+        let assignments = expand_assignments(cmd, shell)?;
+        let mut argv = expand_words(&cmd.words, shell)?;
+        resolve_command(&mut argv, shell)?;
+        argvs.push(argv);
+        envs.push(shell.vars.environ(&assignments));
+        redirects.push(expand_redirects(cmd, shell)?);
+    }
+
+    execute_pipeline(&argvs, &pipeline.to_string(), &envs, &redirects, background, shell)
+}
+
+fn execute_simple(cmd: &SimpleCommand, background: bool, shell: &mut Shell) -> io::Result<i32> {
+    shell.substitution_status = None;
+
+    // A line of only `NAME=value` words sets shell variables left to right, so a
+    // value can use the ones before it
+    if cmd.words.is_empty() && !cmd.assignments.is_empty() {
+        for assignment in &cmd.assignments {
+            let value = expand_word(&assignment.value, shell)?;
+            shell.vars.set(&assignment.name, &value).map_err(io::Error::other)?;
+        }
+        let redirects = expand_redirects(cmd, shell)?;
+        return finish_without_command(&redirects, shell);
+    }
+
+    let assignments = expand_assignments(cmd, shell)?;
+    let mut argv = expand_words(&cmd.words, shell)?;
+    let redirects = expand_redirects(cmd, shell)?;
+
+    // Words that expanded to nothing, or none at all, leave only the assignments
+    // and redirections
+    if argv.is_empty() {
+        for (name, value) in &assignments {
+            shell.vars.set(name, value).map_err(io::Error::other)?;
+        }
+        return finish_without_command(&redirects, shell);
+    }
+
+    // Built-in commands run inside the shell, with their redirections applied
+    // only for the duration of the command
+    if let Some(command) = argv.first()
+        && is_builtin(command)
+    {
+        let saved = redirect_io(&redirects)?;
+        let status = run_builtin_with(&argv, &assignments, shell);
+        saved.restore();
+        return status;
+    }
+
+    resolve_command(&mut argv, shell)?;
+    let env = shell.vars.environ(&assignments);
+
+    // Execute (foreground or background)
+    execute_command(
+        &argv,
+        &cmd.to_string(),
+        &env,
+        &redirects,
+        background,
+        shell,
+    )
+}
+
+/// Finish a command with no command name once its assignments are made: its
+/// redirections are still applied, so `> file` creates or truncates the file, and
+/// then undone. Its status is that of its last command substitution, if any.
+fn finish_without_command(redirects: &[Redirection], shell: &mut Shell) -> io::Result<i32> {
+    redirect_io(redirects)?.restore();
+    Ok(shell.substitution_status.take().unwrap_or(0))
+}
+
+/// Run a builtin with its `NAME=value` prefix assignments in effect only for its duration
+fn run_builtin_with(argv: &[String], assignments: &[(String, String)], shell: &mut Shell) -> io::Result<i32> {
+    let saved: Vec<(String, Option<String>)> = assignments
+        .iter()
+        .map(|(name, _)| (name.clone(), shell.vars.get(name).map(|v| v.to_string())))
+        .collect();
+    for (name, value) in assignments {
+        shell.vars.set(name, value).map_err(io::Error::other)?;
+    }
+
+    let status = execute_builtin(&argv[0], &argv[1..], shell).map_err(io::Error::other);
+
+    for (name, value) in saved {
+        let _ = match value {
+            Some(value) => shell.vars.set(&name, &value),
+            None => shell.vars.unset(&name),
+        };
+    }
+    status
+}
+
+/// Expand each redirection target (or here-document body) and resolve the redirections, in command-line order
+fn expand_redirects(cmd: &SimpleCommand, shell: &mut Shell) -> io::Result<Vec<Redirection>> {
+    let mut redirects = Vec::new();
+    for redirect in &cmd.redirects {
+        let target = match redirect.op {
+            // A here-document body only gets parameter and command substitution
+            Operator::DLess | Operator::DLessDash => match &redirect.here_doc {
+                Some(body) => get_env(body, shell).map_err(io::Error::other)?.text(),
+                None => String::new(),
+            },
+            Operator::TLess => expand_word(&redirect.target, shell)? + "\n",
+            _ => expand_word(&redirect.target, shell)?,
+        };
+        let steps = resolve_redirect(redirect.op, redirect.fd, target, shell.options.noclobber)
+            .map_err(io::Error::other)?;
+        redirects.extend(steps);
+    }
+    Ok(redirects)
+}
+
+/// Expand the values of a command's `NAME=value` prefix assignments
+fn expand_assignments(cmd: &SimpleCommand, shell: &mut Shell) -> io::Result<Vec<(String, String)>> {
+    cmd.assignments
+        .iter()
+        .map(|a| Ok((a.name.clone(), expand_word(&a.value, shell)?)))
+        .collect()
+}
+
+/// Search PATH for the command name if it has no slash and is not a builtin
+fn resolve_command(argv: &mut [String], shell: &Shell) -> io::Result<()> {
+    if let Some(name) = argv.first_mut()
+        && !name.contains('/')
+        && !is_builtin(name)
+    {
+        match search_path(name, shell.vars.get("PATH")) {
+            Some(full_path) => *name = full_path,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{}: command not found", name),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Apply tilde and environment variable expansion, returning the unquoted text
+fn expand_word(word: &Word, shell: &mut Shell) -> io::Result<String> {
+    let word = get_env(&expand_tilde(word, shell), shell).map_err(io::Error::other)?;
+    Ok(word.text())
+}
+
+/// Expand command words: brace expansion, tilde and parameter expansion, then
+/// field splitting of unquoted results, then pathname expansion of each field
+fn expand_words(words: &[Word], shell: &mut Shell) -> io::Result<Vec<String>> {
+    let mut argv = Vec::new();
+    for word in words.iter().flat_map(expand_braces) {
+        let word = get_env(&expand_tilde(&word, shell), shell).map_err(io::Error::other)?;
+        let ifs = shell.vars.get("IFS").unwrap_or(" \t\n").to_string();
+        for field in split_fields(&word, &ifs) {
+            argv.extend(expand_pathname(&field, shell)?);
+        }
+    }
+    Ok(argv)
+}
+
+/// Replace a field containing unquoted `*`, `?` or `[` with the matching paths.
+/// With no match the field is kept as is, dropped (nullglob) or an error (failglob).
+fn expand_pathname(field: &Word, shell: &Shell) -> io::Result<Vec<String>> {
+    let pattern = word_to_pattern(field);
+    if !has_pattern_chars(&pattern) {
+        return Ok(vec![field.text()]);
+    }
+
+    let paths = glob(&pattern, shell.options.globstar);
+    if !paths.is_empty() {
+        Ok(paths)
+    } else if shell.options.failglob {
+        Err(io::Error::other(format!("no match: {}", field.text())))
+    } else if shell.options.nullglob {
+        Ok(Vec::new())
+    } else {
+        Ok(vec![field.text()])
+    }
+}
@@ -0,0 +1,183 @@
+use std::fs;
+use std::path::Path;
+
+use crate::pattern::matches;
+
+/// True if the pattern contains an unescaped `*`, `?` or `[`
+pub fn has_pattern_chars(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Expand a pathname pattern against the filesystem, returning the matching
+/// paths in sorted order. With `globstar`, a `**` component matches any number
+/// of directories (or, as the last component, every file below).
+pub fn glob(pattern: &str, globstar: bool) -> Vec<String> {
+    let components: Vec<&str> = pattern.split('/').filter(|c| !c.is_empty()).collect();
+    let dirs_only = pattern.ends_with('/') && !components.is_empty();
+    let mut paths = vec![if pattern.starts_with('/') { "/".to_string() } else { String::new() }];
+
+    for (i, component) in components.iter().enumerate() {
+        let last = i + 1 == components.len();
+        let mut next = Vec::new();
+
+        for base in &paths {
+            if globstar && *component == "**" {
+                // Zero directories, then everything below the base
+                // As the last component it lists the base itself as a directory, `x/`
+                if !base.is_empty() || !last {
+                    next.push(if last { join(base, "") } else { base.clone() });
+                }
+                walk(base, !last, &mut next);
+            } else if !has_pattern_chars(component) {
+                next.push(join(base, &unescape(component)));
+            } else {
+                for name in read_names(base) {
+                    // Hidden files only match a pattern that starts with a literal '.'
+                    if name.starts_with('.') && !component.starts_with('.') {
+                        continue;
+                    }
+                    if matches(component, &name) {
+                        next.push(join(base, &name));
+                    }
+                }
+            }
+        }
+        paths = next;
+    }
+
+    paths.retain(|p| {
+        let path = Path::new(p);
+        !p.is_empty() && path.exists() && (!dirs_only || path.is_dir())
+    });
+    if dirs_only {
+        for p in paths.iter_mut().filter(|p| !p.ends_with('/')) {
+            p.push('/');
+        }
+    }
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Names in the directory `base` (the current directory when empty)
+fn read_names(base: &str) -> Vec<String> {
+    let dir = if base.is_empty() { "." } else { base };
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().into_string().ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Recursively collect the non-hidden entries below `base` for `**`
+fn walk(base: &str, dirs_only: bool, out: &mut Vec<String>) {
+    for name in read_names(base) {
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = join(base, &name);
+        // Don't follow symlinks, so links back up the tree can't loop forever
+        let is_dir = fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir());
+        if is_dir || !dirs_only {
+            out.push(path.clone());
+        }
+        if is_dir {
+            walk(&path, dirs_only, out);
+        }
+    }
+}
+
+fn join(base: &str, name: &str) -> String {
+    if base.is_empty() {
+        name.to_string()
+    } else if base.ends_with('/') {
+        format!("{}{}", base, name)
+    } else {
+        format!("{}/{}", base, name)
+    }
+}
+
+/// Drop the backslashes that protect literal characters in a pattern
+fn unescape(pattern: &str) -> String {
+    let mut out = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory holding `files`, with any parent directories they need
+    fn tree(name: &str, files: &[&str]) -> String {
+        let root = std::env::temp_dir().join(format!("glob-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        root.to_string_lossy().into_owned()
+    }
+
+    /// The matches of `pattern` below `root`, relative to it
+    fn glob_in(root: &str, pattern: &str, globstar: bool) -> Vec<String> {
+        glob(&format!("{}/{}", root, pattern), globstar)
+            .iter()
+            .map(|p| p[root.len() + 1..].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn has_pattern_chars_ignores_escaped_ones() {
+        assert!(has_pattern_chars("*.rs"));
+        assert!(has_pattern_chars("a[bc]"));
+        assert!(!has_pattern_chars(r"a\*b"));
+        assert!(!has_pattern_chars("plain"));
+    }
+
+    #[test]
+    fn matches_are_sorted_and_skip_hidden_files() {
+        let root = tree("sorted", &["b.rs", "a.rs", "c.txt", ".hidden.rs"]);
+        assert_eq!(glob_in(&root, "*.rs", false), vec!["a.rs", "b.rs"]);
+        assert_eq!(glob_in(&root, ".*.rs", false), vec![".hidden.rs"]);
+        assert!(glob_in(&root, "*.md", false).is_empty());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn patterns_in_directory_components() {
+        let root = tree("dirs", &["src/a.rs", "tests/b.rs", "docs/c.md"]);
+        assert_eq!(glob_in(&root, "*/*.rs", false), vec!["src/a.rs", "tests/b.rs"]);
+        assert_eq!(glob_in(&root, "*/", false), vec!["docs/", "src/", "tests/"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn globstar_matches_any_depth() {
+        let root = tree("globstar", &["a.rs", "x/b.rs", "x/y/c.rs", "x/y/d.md"]);
+        assert_eq!(glob_in(&root, "**/*.rs", true), vec!["a.rs", "x/b.rs", "x/y/c.rs"]);
+        assert_eq!(glob_in(&root, "**/*.rs", false), vec!["x/b.rs"]);
+        assert_eq!(glob_in(&root, "x/**", true), vec!["x/", "x/b.rs", "x/y", "x/y/c.rs", "x/y/d.md"]);
+        assert_eq!(glob_in(&root, "x/**/", true), vec!["x/", "x/y/"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
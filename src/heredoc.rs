@@ -0,0 +1,136 @@
+use std::io;
+
+use crate::lexer::{Operator, Quote, Word, WordPart, lex_here_doc};
+use crate::parser::CommandList;
+
+/// Read the body of every `<<` and `<<-` here-document in the list, in the order they
+/// appear, from the lines that follow the command. `read_line` returns None at end of input.
+/// A Ctrl-C while reading is returned as `ErrorKind::Interrupted`.
+pub fn read_here_docs<F>(list: &mut CommandList, mut read_line: F) -> io::Result<()>
+where
+    F: FnMut() -> io::Result<Option<String>>,
+{
+    let redirects = list
+        .items
+        .iter_mut()
+        .flat_map(|item| {
+            std::iter::once(&mut item.and_or.first)
+                .chain(item.and_or.rest.iter_mut().map(|(_, pipeline)| pipeline))
+        })
+        .flat_map(|pipeline| pipeline.commands.iter_mut())
+        .flat_map(|cmd| cmd.redirects.iter_mut())
+        .filter(|r| matches!(r.op, Operator::DLess | Operator::DLessDash));
+
+    for redirect in redirects {
+        let delimiter = redirect.target.text();
+        let strip_tabs = redirect.op == Operator::DLessDash;
+
+        let mut body = String::new();
+        loop {
+            let Some(line) = read_line()? else {
+                eprintln!(
+                    "warning: here-document delimited by end-of-file (wanted `{}')",
+                    delimiter
+                );
+                break;
+            };
+            let line = if strip_tabs { line.trim_start_matches('\t') } else { &line };
+            if line == delimiter {
+                break;
+            }
+            body.push_str(line);
+            body.push('\n');
+        }
+
+        // Quoting any part of the delimiter turns off expansion in the body
+        redirect.here_doc = Some(if redirect.target.quoted() {
+            Word {
+                parts: vec![WordPart { text: body, quote: Quote::Single, expanded: false }],
+            }
+        } else {
+            lex_here_doc(&body).map_err(io::Error::other)?
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::get_tokens;
+    use crate::parser::{Redirect, parse};
+
+    /// Parse `command` and read its here-documents from `lines`, returning the list
+    /// and the lines left unread
+    fn read(command: &str, lines: &[&str]) -> (CommandList, Vec<String>) {
+        let mut list = parse(&get_tokens(command).unwrap().items).unwrap();
+        let mut lines = lines.iter().map(|line| line.to_string());
+        read_here_docs(&mut list, || Ok(lines.next())).unwrap();
+        (list, lines.collect())
+    }
+
+    fn redirects(list: &CommandList) -> Vec<&Redirect> {
+        list.items
+            .iter()
+            .flat_map(|item| std::iter::once(&item.and_or.first).chain(item.and_or.rest.iter().map(|(_, p)| p)))
+            .flat_map(|pipeline| &pipeline.commands)
+            .flat_map(|cmd| &cmd.redirects)
+            .collect()
+    }
+
+    fn body(redirect: &Redirect) -> &Word {
+        redirect.here_doc.as_ref().unwrap()
+    }
+
+    #[test]
+    fn body_ends_at_the_delimiter_line() {
+        let (list, rest) = read("cat <<EOF", &["one", "  EOF", "EOF", "after"]);
+        assert_eq!(body(redirects(&list)[0]).text(), "one\n  EOF\n");
+        assert_eq!(rest, vec!["after"]);
+    }
+
+    #[test]
+    fn dash_form_strips_leading_tabs() {
+        let (list, _) = read("cat <<-EOF", &["\tone", "\t\ttwo", "  three", "\tEOF"]);
+        assert_eq!(body(redirects(&list)[0]).text(), "one\ntwo\n  three\n");
+    }
+
+    #[test]
+    fn unquoted_delimiter_keeps_expansions_in_the_body() {
+        let (list, _) = read("cat <<EOF", &["$HOME \\$x", "EOF"]);
+        let word = body(redirects(&list)[0]);
+        assert!(word.parts.iter().any(|p| p.quote == Quote::Double && p.text.contains("$HOME")));
+        assert!(word.parts.iter().any(|p| p.quote == Quote::Single && p.text == "$"));
+    }
+
+    #[test]
+    fn quoted_delimiter_makes_the_body_literal() {
+        for command in ["cat <<'EOF'", "cat <<\"EOF\"", "cat <<E\\OF"] {
+            let (list, _) = read(command, &["$HOME `cmd` \\$x", "EOF"]);
+            let word = body(redirects(&list)[0]);
+            assert_eq!(word.parts.len(), 1, "{}", command);
+            assert_eq!(word.parts[0].quote, Quote::Single, "{}", command);
+            assert_eq!(word.parts[0].text, "$HOME `cmd` \\$x\n", "{}", command);
+        }
+    }
+
+    #[test]
+    fn several_here_documents_are_read_in_order() {
+        let (list, _) = read("cat <<A && cat <<-B; cat <<C", &["a", "A", "\tb", "B", "c", "C"]);
+        let bodies: Vec<String> = redirects(&list).iter().map(|r| body(r).text()).collect();
+        assert_eq!(bodies, vec!["a\n", "b\n", "c\n"]);
+    }
+
+    #[test]
+    fn end_of_input_ends_the_body() {
+        let (list, _) = read("cat <<EOF", &["only"]);
+        assert_eq!(body(redirects(&list)[0]).text(), "only\n");
+    }
+
+    #[test]
+    fn other_redirections_get_no_body() {
+        let (list, rest) = read("cat <in >out", &["line"]);
+        assert!(redirects(&list).iter().all(|r| r.here_doc.is_none()));
+        assert_eq!(rest, vec!["line"]);
+    }
+}
@@ -0,0 +1,110 @@
+use nix::errno::Errno;
+use nix::sys::signal::{SigSet, SigmaskHow, Signal, kill, killpg, sigprocmask};
+use nix::sys::wait::{WaitPidFlag, waitpid};
+use nix::unistd::{Pid, getpgrp, getpid, isatty, setpgid, tcsetpgrp};
+
+use crate::background::{Exit, Job, JobState};
+use crate::shell::Shell;
+use crate::signals::take_interrupt;
+
+/// Take charge of the terminal if stdin is one: move the shell into its own process
+/// group and make that the terminal's foreground group. Returns whether job control
+/// is on, which is only the case for an interactive shell.
+pub fn init_job_control() -> bool {
+    if !isatty(0).unwrap_or(false) {
+        return false;
+    }
+    // Fails harmlessly if the shell already leads its own session
+    let _ = setpgid(getpid(), getpid());
+    give_terminal_to(getpgrp());
+    true
+}
+
+/// Put a freshly forked process into its job's process group, or make it the leader
+/// of a new group when it is the job's first process, and give a foreground job the
+/// terminal. Both the shell and the child call this, so it is done before either
+/// one goes on, whichever runs first.
+pub fn place_in_job(pid: Pid, pgid: Option<Pid>, foreground: bool) {
+    let pgid = pgid.unwrap_or(pid);
+    let _ = setpgid(pid, pgid);
+    if foreground {
+        give_terminal_to(pgid);
+    }
+}
+
+/// Make `pgid` the terminal's foreground process group
+pub fn give_terminal_to(pgid: Pid) {
+    // A process outside the foreground group is stopped by SIGTTOU for trying this
+    // unless the signal is blocked
+    let mut block = SigSet::empty();
+    block.add(Signal::SIGTTOU);
+    let mut old = SigSet::empty();
+    let _ = sigprocmask(SigmaskHow::SIG_BLOCK, Some(&block), Some(&mut old));
+    let _ = tcsetpgrp(0, pgid);
+    let _ = sigprocmask(SigmaskHow::SIG_SETMASK, Some(&old), None);
+}
+
+/// Wait for every running process of a job to end, or with `untraced` also to stop,
+/// recording what happened to each. Any child may be collected meanwhile, so
+/// background jobs that change state are recorded too instead of lingering as
+/// zombies, and in notify mode reported at once. Returns false if SIGINT cut the
+/// wait short.
+pub fn wait_for_job(job: &mut Job, untraced: bool, shell: &mut Shell) -> bool {
+    let flags = if untraced { WaitPidFlag::WUNTRACED } else { WaitPidFlag::empty() };
+    while job.processes.iter().any(|p| p.state == JobState::Running) {
+        let status = match waitpid(Pid::from_raw(-1), Some(flags)) {
+            Ok(status) => status,
+            Err(Errno::EINTR) if take_interrupt() => return false,
+            Err(Errno::EINTR) => continue,
+            Err(_) => {
+                // No children left to wait for, so none of these can still be running
+                for process in job.processes.iter_mut().filter(|p| p.state == JobState::Running) {
+                    process.state = JobState::Done(Exit::Code(1));
+                }
+                break;
+            }
+        };
+        let (Some(pid), Some(state)) = (status.pid(), JobState::from_wait(status)) else {
+            continue;
+        };
+        if !job.set_state(pid.as_raw(), state) {
+            shell.bg_manager.record(pid.as_raw(), state);
+            if shell.options.notify {
+                shell.bg_manager.report_changes();
+            }
+        }
+    }
+    true
+}
+
+/// Run a job in the foreground, with the terminal handed to it for the duration when
+/// job control is on, until it ends or is stopped. A stopped job is passed to the
+/// BackgroundManager. Returns each process's exit status.
+pub fn run_foreground(mut job: Job, shell: &mut Shell) -> Vec<i32> {
+    if shell.job_control {
+        give_terminal_to(Pid::from_raw(job.pgid));
+    }
+    // A Ctrl-C that reaches the shell is the job's to act on; keep waiting for it
+    while !wait_for_job(&mut job, shell.job_control, shell) {}
+    if shell.job_control {
+        give_terminal_to(getpgrp());
+    }
+
+    let statuses = job.statuses();
+    if matches!(job.state(), JobState::Stopped(_)) {
+        shell.bg_manager.add_stopped_job(job);
+    }
+    statuses
+}
+
+/// Send a signal to every process of a job: to its process group when job control
+/// gave it one, otherwise to each process the shell started for it
+pub fn signal_job(pgid: Pid, pids: &[Pid], signal: Signal, job_control: bool) -> nix::Result<()> {
+    if job_control {
+        return killpg(pgid, signal);
+    }
+    for &pid in pids {
+        kill(pid, signal)?;
+    }
+    Ok(())
+}
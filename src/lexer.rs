@@ -1,10 +1,141 @@
-use std::io;
+use std::io::{self, IsTerminal};
+use std::iter::Peekable;
 
+use crate::line_editor;
 use crate::prompt;
+use crate::signals::take_interrupt;
+
+/// How a piece of a word was quoted on the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quote {
+    /// Plain text, subject to every expansion
+    None,
+    /// Single-quoted or backslash-escaped text, taken literally
+    Single,
+    /// Double-quoted text, only `$` expansions apply
+    Double,
+}
+
+/// A run of characters inside a word that share the same quoting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WordPart {
+    pub text: String,
+    pub quote: Quote,
+    /// Produced by an unquoted expansion, so still subject to field splitting
+    pub expanded: bool,
+}
+
+/// A single shell word, kept as quoted/unquoted parts so expansions can skip quoted text
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+}
+
+impl Word {
+    /// Append a character, merging it into the last part when the quoting matches
+    fn push(&mut self, c: char, quote: Quote) {
+        match self.parts.last_mut() {
+            Some(part) if part.quote == quote => part.text.push(c),
+            _ => self.parts.push(WordPart {
+                text: c.to_string(),
+                quote,
+                expanded: false,
+            }),
+        }
+    }
+
+    /// Start an (initially empty) part, so `""` and `''` still produce a word
+    fn open(&mut self, quote: Quote) {
+        self.parts.push(WordPart {
+            text: String::new(),
+            quote,
+            expanded: false,
+        });
+    }
+
+    /// True if any part of the word was quoted or escaped
+    pub fn quoted(&self) -> bool {
+        self.parts.iter().any(|p| p.quote != Quote::None)
+    }
+
+    /// The word with quotes removed
+    pub fn text(&self) -> String {
+        self.parts.iter().map(|p| p.text.as_str()).collect()
+    }
+}
+
+/// Control and redirection operators recognised by the lexer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Pipe,      // |
+    OrIf,      // ||
+    Amp,       // &
+    AndIf,     // &&
+    Semi,      // ;
+    Less,      // <
+    Great,     // >
+    DGreat,    // >>
+    DLess,     // <<
+    DLessDash, // <<-
+    TLess,     // <<<
+    LessGreat, // <>
+    LessAnd,   // <&
+    GreatAnd,  // >&
+    Clobber,   // >|
+    AndGreat,  // &>
+    AndDGreat, // &>>
+}
+
+/// Every operator spelling, longest first so the lexer can match greedily
+const OPERATORS: &[(&str, Operator)] = &[
+    ("&>>", Operator::AndDGreat),
+    ("<<-", Operator::DLessDash),
+    ("<<<", Operator::TLess),
+    ("||", Operator::OrIf),
+    ("&&", Operator::AndIf),
+    (">>", Operator::DGreat),
+    ("<<", Operator::DLess),
+    ("<>", Operator::LessGreat),
+    ("<&", Operator::LessAnd),
+    (">&", Operator::GreatAnd),
+    (">|", Operator::Clobber),
+    ("&>", Operator::AndGreat),
+    ("|", Operator::Pipe),
+    ("&", Operator::Amp),
+    (";", Operator::Semi),
+    ("<", Operator::Less),
+    (">", Operator::Great),
+];
+
+impl Operator {
+    pub fn as_str(&self) -> &'static str {
+        OPERATORS
+            .iter()
+            .find(|(_, op)| op == self)
+            .map(|(s, _)| *s)
+            .unwrap_or("")
+    }
+
+    /// True for operators that redirect a file descriptor rather than separate commands
+    pub fn is_redirection(&self) -> bool {
+        !matches!(
+            self,
+            Operator::Pipe | Operator::OrIf | Operator::Amp | Operator::AndIf | Operator::Semi
+        )
+    }
+}
+
+/// A lexed token: a word, an operator, or the fd number written directly before a redirection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Word(Word),
+    Op(Operator),
+    IoNumber(i32),
+}
 
 #[derive(Debug, Default, Clone)]
 pub struct TokenList {
-    pub items: Vec<String>,
+    pub items: Vec<Token>,
 }
 
 impl TokenList {
@@ -12,19 +143,49 @@ impl TokenList {
         Self { items: Vec::new() }
     }
 
-    pub fn add_token(&mut self, item: &str) {
-        self.items.push(item.to_string());
+    pub fn add_token(&mut self, item: Token) {
+        self.items.push(item);
     }
 }
 
 /// Prints prompt, reads input, trims newline/CRLF, returns input
-pub fn get_input() -> io::Result<String> {
+/// At a terminal the line can be edited, and Up/Down recall earlier lines of `history`.
+/// `on_wake` is called when a child changes state or a trapped signal arrives while
+/// the shell waits, and returns whether it printed anything, in which case the prompt
+/// is shown again.
+pub fn get_input(history: &[String], on_wake: impl FnMut() -> bool) -> io::Result<String> {
+    match read_line(&prompt::build_prompt(), history, on_wake)? {
+        Some(line) => Ok(line),
+        // Ctrl-D or end of a piped script
+        None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "exit")),
+    }
+}
+
+/// Read one more line of a command that continues past the first, such as the body
+/// of a here-document. Returns None at end of input.
+pub fn get_continuation_line() -> io::Result<Option<String>> {
+    // Job reports wait for the next full prompt
+    read_line("> ", &[], || false)
+}
+
+fn read_line(prompt: &str, history: &[String], on_wake: impl FnMut() -> bool) -> io::Result<Option<String>> {
     use std::io::Write;
-    print!("{}", prompt::build_prompt());
-    io::stdout().flush()?; // make sure prompt is shown before reading
+    // A Ctrl-C from before this line was started is not meant for it
+    take_interrupt();
 
     let mut line = String::new();
-    io::stdin().read_line(&mut line)?;
+    if io::stdin().is_terminal() {
+        match line_editor::read_line(prompt, history, on_wake)? {
+            Some(text) => line = text,
+            None => return Ok(None),
+        }
+    } else {
+        print!("{}", prompt);
+        io::stdout().flush()?; // make sure prompt is shown before reading
+        if io::stdin().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+    }
 
     if line.ends_with('\n') {
         line.pop();
@@ -33,16 +194,318 @@ pub fn get_input() -> io::Result<String> {
         }
     }
 
-    Ok(line)
+    Ok(Some(line))
 }
 
-/// Split input into tokens by space, collapsing consecutive spaces
-pub fn get_tokens(input: &str) -> TokenList {
+type Chars<'a> = Peekable<std::str::Chars<'a>>;
+
+/// Split input into words and operators, honouring '...', "..." and backslash escapes.
+/// Operators are recognised whether or not they are surrounded by whitespace.
+pub fn get_tokens(input: &str) -> Result<TokenList, String> {
     let mut tokens = TokenList::new();
+    let mut current: Option<Word> = None;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(word) = current.take() {
+                    tokens.add_token(Token::Word(word));
+                }
+            }
+            '|' | '&' | ';' | '<' | '>' => {
+                let mut spelling = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    spelling.push(next);
+                    if OPERATORS.iter().any(|(s, _)| s.starts_with(spelling.as_str())) {
+                        chars.next();
+                    } else {
+                        spelling.pop();
+                        break;
+                    }
+                }
+                let op = OPERATORS
+                    .iter()
+                    .find(|(s, _)| *s == spelling)
+                    .map(|(_, op)| *op)
+                    .unwrap_or(Operator::Pipe);
+
+                if let Some(word) = current.take() {
+                    // An unquoted number right before a redirection names the fd, as in `2>`
+                    let text = word.text();
+                    let takes_fd = op.is_redirection()
+                        && !matches!(op, Operator::AndGreat | Operator::AndDGreat)
+                        && !word.quoted()
+                        && text.bytes().all(|b| b.is_ascii_digit());
+                    match text.parse::<i32>() {
+                        Ok(fd) if takes_fd => tokens.add_token(Token::IoNumber(fd)),
+                        _ => tokens.add_token(Token::Word(word)),
+                    }
+                }
+                tokens.add_token(Token::Op(op));
+            }
+            c => lex_word_char(c, &mut chars, current.get_or_insert_with(Word::default))?,
+        }
+    }
 
-    for part in input.split(' ').filter(|s| !s.is_empty()) {
-        tokens.add_token(part);
+    if let Some(word) = current.take() {
+        tokens.add_token(Token::Word(word));
     }
 
-    tokens
+    Ok(tokens)
+}
+
+/// Read text as a single word: quotes and escapes are handled, but whitespace and
+/// operator characters are kept literally. Used for the operands of `${VAR:-word}`.
+pub fn parse_word(text: &str) -> Result<Word, String> {
+    let mut word = Word::default();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        lex_word_char(c, &mut chars, &mut word)?;
+    }
+    Ok(word)
+}
+
+/// Add one (possibly quoted or escaped) piece of a word, starting at `c`
+fn lex_word_char(c: char, chars: &mut Chars, word: &mut Word) -> Result<(), String> {
+    match c {
+        '\\' => {
+            // A trailing backslash is a line continuation, which we simply drop
+            if let Some(next) = chars.next() {
+                word.push(next, Quote::Single);
+            }
+        }
+        '\'' => {
+            word.open(Quote::Single);
+            loop {
+                match chars.next() {
+                    Some('\'') => break,
+                    Some(c) => word.push(c, Quote::Single),
+                    None => return Err("unexpected EOF while looking for matching `''".into()),
+                }
+            }
+        }
+        '"' => {
+            word.open(Quote::Double);
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    // Inside double quotes a backslash only escapes $ ` " \
+                    Some('\\') => match chars.peek() {
+                        Some(&e @ ('$' | '`' | '"' | '\\')) => {
+                            chars.next();
+                            word.push(e, Quote::Single);
+                        }
+                        _ => word.push('\\', Quote::Double),
+                    },
+                    Some('$') => lex_dollar(chars, word, Quote::Double)?,
+                    Some('`') => lex_backquote(chars, word, Quote::Double)?,
+                    Some(c) => word.push(c, Quote::Double),
+                    None => return Err("unexpected EOF while looking for matching `\"'".into()),
+                }
+            }
+        }
+        '$' => lex_dollar(chars, word, Quote::None)?,
+        '`' => lex_backquote(chars, word, Quote::None)?,
+        c => word.push(c, Quote::None),
+    }
+    Ok(())
+}
+
+/// Turn the body of an unquoted here-document into a word. As inside double quotes,
+/// `$` and backquote expansions apply and a backslash only escapes `$`, `` ` ``, `\`
+/// and a newline, but quote characters are ordinary text.
+pub fn lex_here_doc(body: &str) -> Result<Word, String> {
+    let mut word = Word::default();
+    word.open(Quote::Double);
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.peek() {
+                Some('\n') => {
+                    chars.next();
+                }
+                Some(&e @ ('$' | '`' | '\\')) => {
+                    chars.next();
+                    word.push(e, Quote::Single);
+                }
+                _ => word.push('\\', Quote::Double),
+            },
+            '$' => lex_dollar(&mut chars, &mut word, Quote::Double)?,
+            '`' => lex_backquote(&mut chars, &mut word, Quote::Double)?,
+            c => word.push(c, Quote::Double),
+        }
+    }
+    Ok(word)
+}
+
+/// Copy a `$` and, for `${...}` and `$(...)`, everything up to the matching brace or
+/// parenthesis into the word verbatim, so spaces, quotes and operators inside stay
+/// part of the expansion
+fn lex_dollar(chars: &mut Chars, word: &mut Word, quote: Quote) -> Result<(), String> {
+    word.push('$', quote);
+    let (open, close) = match chars.peek() {
+        Some('{') => ('{', '}'),
+        Some('(') => ('(', ')'),
+        _ => return Ok(()),
+    };
+
+    let mut depth = 0;
+    let mut in_single = false;
+    let mut in_double = false;
+    while let Some(c) = chars.next() {
+        word.push(c, quote);
+        match c {
+            '\\' if !in_single => {
+                if let Some(next) = chars.next() {
+                    word.push(next, quote);
+                }
+            }
+            '\'' if !in_double => in_single = !in_single,
+            '"' if !in_single => in_double = !in_double,
+            c if c == open && !in_single && !in_double => depth += 1,
+            c if c == close && !in_single && !in_double => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(());
+                }
+            }
+            _ => {}
+        }
+    }
+    Err(format!("unexpected EOF while looking for matching `{}'", close))
+}
+
+/// Copy a `` `command` `` substitution into the word verbatim, up to the closing backquote
+fn lex_backquote(chars: &mut Chars, word: &mut Word, quote: Quote) -> Result<(), String> {
+    word.push('`', quote);
+    while let Some(c) = chars.next() {
+        word.push(c, quote);
+        match c {
+            '\\' => {
+                if let Some(next) = chars.next() {
+                    word.push(next, quote);
+                }
+            }
+            '`' => return Ok(()),
+            _ => {}
+        }
+    }
+    Err("unexpected EOF while looking for matching ``'".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str) -> Token {
+        Token::Word(Word {
+            parts: vec![WordPart {
+                text: text.to_string(),
+                quote: Quote::None,
+                expanded: false,
+            }],
+        })
+    }
+
+    fn tokens(input: &str) -> Vec<Token> {
+        get_tokens(input).unwrap().items
+    }
+
+    #[test]
+    fn operators_need_no_surrounding_spaces() {
+        assert_eq!(
+            tokens("a|b&&c||d;e&"),
+            vec![
+                word("a"),
+                Token::Op(Operator::Pipe),
+                word("b"),
+                Token::Op(Operator::AndIf),
+                word("c"),
+                Token::Op(Operator::OrIf),
+                word("d"),
+                Token::Op(Operator::Semi),
+                word("e"),
+                Token::Op(Operator::Amp),
+            ]
+        );
+    }
+
+    #[test]
+    fn operators_match_longest_spelling() {
+        assert_eq!(
+            tokens("cat<<-EOF>>out&>>log"),
+            vec![
+                word("cat"),
+                Token::Op(Operator::DLessDash),
+                word("EOF"),
+                Token::Op(Operator::DGreat),
+                word("out"),
+                Token::Op(Operator::AndDGreat),
+                word("log"),
+            ]
+        );
+    }
+
+    #[test]
+    fn digits_before_a_redirection_are_an_io_number() {
+        assert_eq!(
+            tokens("cmd 2>err 3<&0"),
+            vec![
+                word("cmd"),
+                Token::IoNumber(2),
+                Token::Op(Operator::Great),
+                word("err"),
+                Token::IoNumber(3),
+                Token::Op(Operator::LessAnd),
+                word("0"),
+            ]
+        );
+    }
+
+    #[test]
+    fn digits_stay_a_word_when_spaced_quoted_or_not_before_a_redirection() {
+        assert_eq!(tokens("echo 2 >f")[1], word("2"));
+        assert!(matches!(tokens("echo '2'>f")[1], Token::Word(_)));
+        assert_eq!(tokens("echo 1|cat")[1], word("1"));
+        assert_eq!(tokens("echo 2&>f")[1], word("2"));
+        assert_eq!(tokens("echo a2>f")[1], word("a2"));
+    }
+
+    #[test]
+    fn quotes_join_into_one_word_and_keep_their_quoting() {
+        let items = tokens(r#"a'b c'"d"\e"#);
+        assert_eq!(items.len(), 1);
+        let Token::Word(word) = &items[0] else { panic!("expected a word") };
+        assert_eq!(word.text(), "ab cde");
+        let quotes: Vec<Quote> = word.parts.iter().map(|p| p.quote).collect();
+        assert_eq!(quotes, vec![Quote::None, Quote::Single, Quote::Double, Quote::Single]);
+    }
+
+    #[test]
+    fn empty_quotes_still_make_a_word() {
+        let items = tokens("echo ''");
+        assert_eq!(items.len(), 2);
+        let Token::Word(word) = &items[1] else { panic!("expected a word") };
+        assert!(word.quoted());
+        assert_eq!(word.text(), "");
+    }
+
+    #[test]
+    fn substitutions_keep_spaces_and_operators_inside() {
+        let items = tokens("echo $(a | b; c) ${x:-y z}");
+        assert_eq!(items.len(), 3);
+        let Token::Word(word) = &items[1] else { panic!("expected a word") };
+        assert_eq!(word.text(), "$(a | b; c)");
+        let Token::Word(word) = &items[2] else { panic!("expected a word") };
+        assert_eq!(word.text(), "${x:-y z}");
+    }
+
+    #[test]
+    fn unterminated_quotes_are_errors() {
+        assert!(get_tokens("echo 'abc").is_err());
+        assert!(get_tokens("echo \"abc").is_err());
+        assert!(get_tokens("echo $(abc").is_err());
+        assert!(get_tokens("echo `abc").is_err());
+    }
 }
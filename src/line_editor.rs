@@ -0,0 +1,517 @@
+use nix::errno::Errno;
+use nix::libc;
+use nix::poll::{PollFd, PollFlags, poll};
+use nix::sys::termios::{LocalFlags, SetArg, SpecialCharacterIndices, Termios, tcgetattr, tcsetattr};
+use nix::unistd::read;
+use std::io::{self, Write};
+
+use crate::signals::{child_pipe, drain_child_pipe, take_interrupt};
+
+/// How long to wait for the rest of an escape sequence or a UTF-8 character, in
+/// milliseconds; a lone Escape key press sends nothing more
+const SEQUENCE_TIMEOUT: i32 = 50;
+
+/// A key press, decoded from the bytes the terminal sends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    /// Control plus a letter, given in lower case
+    Ctrl(char),
+    /// Alt (or Escape, then the key) plus a letter or digit, given in lower case
+    Alt(char),
+    Enter,
+    Backspace,
+    AltBackspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    WordLeft,
+    WordRight,
+    Unknown,
+}
+
+/// The terminal's settings as the shell found them, and the raw mode the editor reads
+/// keys in. The original settings come back when this is dropped.
+struct Terminal {
+    cooked: Termios,
+    raw: Termios,
+}
+
+impl Terminal {
+    /// Switch the terminal to raw mode: keys arrive one at a time and are not echoed.
+    /// Ctrl-C still raises SIGINT.
+    fn enter_raw() -> io::Result<Self> {
+        let cooked = tcgetattr(io::stdin())?;
+        let mut raw = cooked.clone();
+        raw.local_flags.remove(LocalFlags::ICANON | LocalFlags::ECHO | LocalFlags::IEXTEN);
+        raw.control_chars[SpecialCharacterIndices::VMIN as usize] = 1;
+        raw.control_chars[SpecialCharacterIndices::VTIME as usize] = 0;
+        let terminal = Terminal { cooked, raw };
+        terminal.set(&terminal.raw)?;
+        Ok(terminal)
+    }
+
+    fn set(&self, termios: &Termios) -> io::Result<()> {
+        tcsetattr(io::stdin(), SetArg::TCSADRAIN, termios)?;
+        Ok(())
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.set(&self.cooked);
+    }
+}
+
+/// The line being edited and where it is shown on the screen
+struct Editor<'a> {
+    prompt: &'a str,
+    history: &'a [String],
+    terminal: Terminal,
+    buffer: Vec<char>,
+    /// Index in `buffer` of the character under the cursor
+    cursor: usize,
+    /// Screen row of the cursor, counted from the row where the prompt's last line starts
+    cursor_row: usize,
+    /// Entry of `history` being shown; `history.len()` for the line being typed
+    history_index: usize,
+    /// The line being typed, kept while history entries are shown in its place
+    stash: Vec<char>,
+    /// Text removed by the last Ctrl-K, Ctrl-U, Ctrl-W or Alt-D, for Ctrl-Y
+    killed: Vec<char>,
+}
+
+/// Read a line from the terminal with Emacs-style editing: Ctrl-A/E/B/F and the arrow,
+/// Home and End keys move, Alt-B/F and Ctrl-arrows move by word, Ctrl-K/U/W and Alt-D
+/// kill text that Ctrl-Y puts back, and Up/Down or Ctrl-P/N walk through `history`.
+/// Ctrl-D on an empty line, or end of input, returns None. SIGINT abandons the line
+/// with `ErrorKind::Interrupted`, and a SIGCHLD or another trapped signal runs
+/// `on_wake`, which returns whether it printed something that needs the line redrawn.
+pub fn read_line(prompt: &str, history: &[String], mut on_wake: impl FnMut() -> bool) -> io::Result<Option<String>> {
+    let mut editor = Editor {
+        prompt,
+        history,
+        terminal: Terminal::enter_raw()?,
+        buffer: Vec::new(),
+        cursor: 0,
+        cursor_row: 0,
+        history_index: history.len(),
+        stash: Vec::new(),
+        killed: Vec::new(),
+    };
+    editor.redraw()?;
+
+    loop {
+        let key = match editor.read_key(&mut on_wake) {
+            Ok(Some(key)) => key,
+            Ok(None) if editor.buffer.is_empty() => return Ok(None),
+            Ok(None) => break,
+            Err(e) => {
+                if e.kind() == io::ErrorKind::Interrupted {
+                    editor.cursor = editor.buffer.len();
+                    editor.refresh()?;
+                    print!("^C");
+                    io::stdout().flush()?;
+                }
+                return Err(e);
+            }
+        };
+
+        match key {
+            Key::Enter => break,
+            Key::Ctrl('d') if editor.buffer.is_empty() => return Ok(None),
+            Key::Char(c) => editor.insert(&[c]),
+            Key::Ctrl('a') | Key::Home => editor.cursor = 0,
+            Key::Ctrl('e') | Key::End => editor.cursor = editor.buffer.len(),
+            Key::Ctrl('b') | Key::Left => editor.cursor = editor.previous_boundary(editor.cursor),
+            Key::Ctrl('f') | Key::Right => editor.cursor = editor.next_boundary(editor.cursor),
+            Key::Alt('b') | Key::WordLeft => editor.cursor = editor.word_start(editor.cursor, is_word_char),
+            Key::Alt('f') | Key::WordRight => editor.cursor = editor.word_end(editor.cursor),
+            Key::Ctrl('h') | Key::Backspace => {
+                let start = editor.previous_boundary(editor.cursor);
+                editor.buffer.drain(start..editor.cursor);
+                editor.cursor = start;
+            }
+            Key::Ctrl('d') | Key::Delete => {
+                let end = editor.next_boundary(editor.cursor);
+                editor.buffer.drain(editor.cursor..end);
+            }
+            Key::Ctrl('k') => editor.kill(editor.cursor, editor.buffer.len()),
+            Key::Ctrl('u') => editor.kill(0, editor.cursor),
+            Key::Ctrl('w') => {
+                let start = editor.word_start(editor.cursor, |c| !c.is_whitespace());
+                editor.kill(start, editor.cursor);
+            }
+            Key::Alt('d') => editor.kill(editor.cursor, editor.word_end(editor.cursor)),
+            Key::AltBackspace => editor.kill(editor.word_start(editor.cursor, is_word_char), editor.cursor),
+            Key::Ctrl('y') => editor.insert(&editor.killed.clone()),
+            Key::Ctrl('t') => editor.transpose(),
+            Key::Ctrl('p') | Key::Up => editor.show_history(editor.history_index.saturating_sub(1)),
+            Key::Ctrl('n') | Key::Down => editor.show_history(editor.history_index + 1),
+            Key::Ctrl('l') => {
+                print!("\x1b[H\x1b[2J");
+                editor.redraw()?;
+            }
+            _ => {}
+        }
+        editor.refresh()?;
+    }
+
+    // Leave the cursor below the whole line before the command runs
+    editor.cursor = editor.buffer.len();
+    editor.refresh()?;
+    print!("\r\n");
+    io::stdout().flush()?;
+    Ok(Some(editor.buffer.iter().collect()))
+}
+
+impl Editor<'_> {
+    /// Wait for the next key press. Returns None at end of input.
+    fn read_key(&mut self, on_wake: &mut dyn FnMut() -> bool) -> io::Result<Option<Key>> {
+        let Some(byte) = self.read_byte(on_wake)? else {
+            return Ok(None);
+        };
+        let key = match byte {
+            b'\r' | b'\n' => Key::Enter,
+            0x7f | 0x08 => Key::Backspace,
+            0x1b => self.read_escape()?,
+            0x01..=0x1a => Key::Ctrl((b'a' + byte - 1) as char),
+            0x00..=0x1f => Key::Unknown,
+            _ => self.read_utf8(byte)?,
+        };
+        Ok(Some(key))
+    }
+
+    /// Wait for a byte from the terminal, running `on_wake` whenever a child changes
+    /// state or a trapped signal arrives in the meantime
+    fn read_byte(&mut self, on_wake: &mut dyn FnMut() -> bool) -> io::Result<Option<u8>> {
+        let stdin = io::stdin();
+        loop {
+            // The SIGINT handler restarts read(), but poll() always fails with EINTR
+            let mut fds = vec![PollFd::new(&stdin, PollFlags::POLLIN)];
+            if let Some(pipe) = child_pipe() {
+                fds.push(PollFd::new(pipe, PollFlags::POLLIN));
+            }
+            let readable = |fd: &PollFd| fd.revents().is_some_and(|r| !r.is_empty());
+            let (woken, ready) = match poll(&mut fds, -1) {
+                Err(Errno::EINTR) => {
+                    if take_interrupt() {
+                        return Err(io::Error::from(io::ErrorKind::Interrupted));
+                    }
+                    (true, false)
+                }
+                Err(e) => return Err(e.into()),
+                Ok(_) => (fds.get(1).is_some_and(readable), readable(&fds[0])),
+            };
+            drop(fds);
+
+            if woken {
+                drain_child_pipe();
+                self.wake(on_wake)?;
+            }
+            if !ready {
+                continue;
+            }
+
+            let mut byte = [0u8];
+            match read(0, &mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(Errno::EINTR) => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// The next byte of a sequence already under way, or None if none follows shortly
+    fn read_following(&self) -> io::Result<Option<u8>> {
+        let stdin = io::stdin();
+        let mut fds = [PollFd::new(&stdin, PollFlags::POLLIN)];
+        match poll(&mut fds, SEQUENCE_TIMEOUT) {
+            Ok(n) if n > 0 => {}
+            Ok(_) | Err(Errno::EINTR) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let mut byte = [0u8];
+        match read(0, &mut byte) {
+            Ok(1) => Ok(Some(byte[0])),
+            Ok(_) | Err(Errno::EINTR) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Decode what follows an Escape: a CSI or SS3 sequence for a cursor or editing
+    /// key, or a key pressed with Alt
+    fn read_escape(&self) -> io::Result<Key> {
+        let Some(next) = self.read_following()? else {
+            return Ok(Key::Unknown);
+        };
+        let key = match next {
+            b'[' | b'O' => {
+                // Parameters, then a final byte in @..~
+                let mut sequence = String::new();
+                while let Some(byte) = self.read_following()? {
+                    sequence.push(byte as char);
+                    if (0x40..=0x7e).contains(&byte) {
+                        break;
+                    }
+                }
+                match sequence.as_str() {
+                    "A" => Key::Up,
+                    "B" => Key::Down,
+                    "C" => Key::Right,
+                    "D" => Key::Left,
+                    "H" | "1~" | "7~" => Key::Home,
+                    "F" | "4~" | "8~" => Key::End,
+                    "3~" => Key::Delete,
+                    "1;5C" | "1;3C" => Key::WordRight,
+                    "1;5D" | "1;3D" => Key::WordLeft,
+                    _ => Key::Unknown,
+                }
+            }
+            0x7f | 0x08 => Key::AltBackspace,
+            byte if byte.is_ascii_alphanumeric() => Key::Alt(byte.to_ascii_lowercase() as char),
+            _ => Key::Unknown,
+        };
+        Ok(key)
+    }
+
+    /// Decode a character whose UTF-8 encoding starts with `first`
+    fn read_utf8(&self, first: u8) -> io::Result<Key> {
+        let len = match first {
+            0x00..=0x7f => 1,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => return Ok(Key::Unknown),
+        };
+        let mut bytes = vec![first];
+        while bytes.len() < len {
+            match self.read_following()? {
+                Some(byte) => bytes.push(byte),
+                None => return Ok(Key::Unknown),
+            }
+        }
+        Ok(match std::str::from_utf8(&bytes).ok().and_then(|s| s.chars().next()) {
+            Some(c) => Key::Char(c),
+            None => Key::Unknown,
+        })
+    }
+
+    /// Run `on_wake` with the terminal back in its usual mode, since it may run
+    /// commands, and redraw the line below anything it printed
+    fn wake(&mut self, on_wake: &mut dyn FnMut() -> bool) -> io::Result<()> {
+        self.terminal.set(&self.terminal.cooked)?;
+        let printed = on_wake();
+        self.terminal.set(&self.terminal.raw)?;
+        if printed {
+            self.redraw()?;
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, text: &[char]) {
+        self.buffer.splice(self.cursor..self.cursor, text.iter().copied());
+        self.cursor += text.len();
+    }
+
+    /// Remove `start..end`, keeping it for Ctrl-Y
+    fn kill(&mut self, start: usize, end: usize) {
+        if start < end {
+            self.killed = self.buffer.drain(start..end).collect();
+            self.cursor = start;
+        }
+    }
+
+    /// Swap the two characters before the cursor, or around it mid-line
+    fn transpose(&mut self) {
+        if self.cursor == 0 || self.buffer.len() < 2 {
+            return;
+        }
+        if self.cursor < self.buffer.len() {
+            self.cursor += 1;
+        }
+        self.buffer.swap(self.cursor - 2, self.cursor - 1);
+    }
+
+    /// Replace the line with history entry `index`, or with the line being typed
+    /// once past the newest entry
+    fn show_history(&mut self, index: usize) {
+        if index == self.history_index || index > self.history.len() {
+            return;
+        }
+        if self.history_index == self.history.len() {
+            self.stash = std::mem::take(&mut self.buffer);
+        }
+        self.history_index = index;
+        self.buffer = match self.history.get(index) {
+            Some(entry) => entry.chars().collect(),
+            None => std::mem::take(&mut self.stash),
+        };
+        self.cursor = self.buffer.len();
+    }
+
+    /// The position one character left of `index`, stepping over combining marks
+    /// along with the character they belong to
+    fn previous_boundary(&self, mut index: usize) -> usize {
+        while index > 0 {
+            index -= 1;
+            if char_width(self.buffer[index]) > 0 {
+                break;
+            }
+        }
+        index
+    }
+
+    /// The position one character right of `index`, combining marks included
+    fn next_boundary(&self, mut index: usize) -> usize {
+        if index < self.buffer.len() {
+            index += 1;
+        }
+        while index < self.buffer.len() && char_width(self.buffer[index]) == 0 {
+            index += 1;
+        }
+        index
+    }
+
+    /// The start of the word before `index`, where a word is a run of characters
+    /// matching `in_word`
+    fn word_start(&self, mut index: usize, in_word: impl Fn(char) -> bool) -> usize {
+        while index > 0 && !in_word(self.buffer[index - 1]) {
+            index -= 1;
+        }
+        while index > 0 && in_word(self.buffer[index - 1]) {
+            index -= 1;
+        }
+        index
+    }
+
+    /// The end of the word at or after `index`
+    fn word_end(&self, mut index: usize) -> usize {
+        while index < self.buffer.len() && !is_word_char(self.buffer[index]) {
+            index += 1;
+        }
+        while index < self.buffer.len() && is_word_char(self.buffer[index]) {
+            index += 1;
+        }
+        index
+    }
+
+    /// Screen row and column of buffer position `index`, relative to the start of
+    /// the prompt's last line, as the terminal wraps the line at `columns`
+    fn position(&self, index: usize, columns: usize) -> (usize, usize) {
+        let prompt_width: usize = last_line(self.prompt).chars().map(char_width).sum();
+        let (mut row, mut column) = (prompt_width / columns, prompt_width % columns);
+        for &c in &self.buffer[..index] {
+            let width = char_width(c);
+            // A wide character that does not fit goes to the next row whole
+            if column + width > columns {
+                row += 1;
+                column = 0;
+            }
+            column += width;
+            if column == columns {
+                row += 1;
+                column = 0;
+            }
+        }
+        (row, column)
+    }
+
+    /// Print the whole prompt on a fresh line, then the line being edited
+    fn redraw(&mut self) -> io::Result<()> {
+        let prompt = self.prompt;
+        print!("{}", &prompt[..prompt.len() - last_line(prompt).len()].replace('\n', "\r\n"));
+        self.cursor_row = 0;
+        self.refresh()
+    }
+
+    /// Rewrite the prompt's last line and the line being edited in place, then put
+    /// the cursor where it belongs
+    fn refresh(&mut self) -> io::Result<()> {
+        let columns = terminal_width();
+        let mut out = String::new();
+        if self.cursor_row > 0 {
+            out.push_str(&format!("\x1b[{}A", self.cursor_row));
+        }
+        out.push_str("\r\x1b[J");
+        out.push_str(last_line(self.prompt));
+        out.extend(&self.buffer);
+
+        let end = self.position(self.buffer.len(), columns);
+        if end.0 > 0 && end.1 == 0 {
+            // The terminal keeps the cursor on the last column of a full row until the
+            // next character; move it to the next row to match
+            out.push_str("\r\n");
+        }
+        let (row, column) = self.position(self.cursor, columns);
+        if end.0 > row {
+            out.push_str(&format!("\x1b[{}A", end.0 - row));
+        }
+        out.push('\r');
+        if column > 0 {
+            out.push_str(&format!("\x1b[{}C", column));
+        }
+        self.cursor_row = row;
+
+        let mut stdout = io::stdout();
+        stdout.write_all(out.as_bytes())?;
+        stdout.flush()
+    }
+}
+
+/// The part of the prompt after its last newline, which is redrawn with the line
+fn last_line(prompt: &str) -> &str {
+    prompt.rsplit('\n').next().unwrap_or(prompt)
+}
+
+/// Characters that make up a word for Alt-B, Alt-F and Alt-D
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Width of the terminal in columns, or 80 if it cannot be told
+fn terminal_width() -> usize {
+    let mut size = libc::winsize { ws_row: 0, ws_col: 0, ws_xpixel: 0, ws_ypixel: 0 };
+    // SAFETY: TIOCGWINSZ only fills in the winsize it is given
+    let result = unsafe { libc::ioctl(libc::STDIN_FILENO, libc::TIOCGWINSZ, &mut size) };
+    if result == 0 && size.ws_col > 0 { size.ws_col as usize } else { 80 }
+}
+
+/// Number of columns a character takes on the terminal: 0 for combining marks and
+/// other zero-width characters, 2 for East Asian wide characters and emoji, else 1
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036f
+        | 0x0483..=0x0489
+        | 0x0591..=0x05bd
+        | 0x0610..=0x061a
+        | 0x064b..=0x065f
+        | 0x1ab0..=0x1aff
+        | 0x1dc0..=0x1dff
+        | 0x200b..=0x200f
+        | 0x20d0..=0x20ff
+        | 0xfe00..=0xfe0f
+        | 0xfe20..=0xfe2f => 0,
+        0x1100..=0x115f
+        | 0x2e80..=0x303e
+        | 0x3041..=0x33ff
+        | 0x3400..=0x4dbf
+        | 0x4e00..=0x9fff
+        | 0xa000..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x1f300..=0x1f64f
+        | 0x1f900..=0x1f9ff
+        | 0x20000..=0x2fffd
+        | 0x30000..=0x3fffd => 2,
+        _ => 1,
+    }
+}
@@ -1,171 +1,119 @@
 extern crate nix;
 
+mod brace;
 mod environment;
+mod executor;
+mod glob;
+mod heredoc;
+mod job_control;
 mod lexer;
+mod line_editor;
+mod parser;
+mod pattern;
 mod path_search;
 mod prompt;
+mod shell;
+mod signals;
 mod tilde;
+mod trap;
 mod redirection;
 mod piping;
 mod background;
 mod builtin;
 
-use environment::get_env;
-use lexer::{get_input, get_tokens, TokenList};
-use path_search::search_path;
+use executor::execute_list;
+use heredoc::read_here_docs;
+use job_control::init_job_control;
+use lexer::{get_continuation_line, get_input, get_tokens};
+use parser::parse;
+use shell::Shell;
+use signals::init_signals;
 use std::io;
-use tilde::expand_tilde;
-use redirection::{execute_command, parse_redirection};
-use piping::execute_pipeline;
-use background::BackgroundManager;
-use builtin::execute_builtin;
+use std::process;
+use trap::{run_exit_trap, run_pending_traps};
 
 fn main() -> io::Result<()> {
-    let mut bg_manager = BackgroundManager::new();
-    let mut history: Vec<String> = Vec::new();
+    let mut shell = Shell::new();
+    shell.job_control = init_job_control();
+    if shell.job_control {
+        init_signals();
+    }
 
     loop {
-        bg_manager.check_and_cleanup_jobs();
+        shell.bg_manager.check_and_cleanup_jobs();
+        run_pending_traps(&mut shell);
+        if shell.exit_requested {
+            break;
+        }
 
-        match get_input() {
+        let notify = shell.options.notify;
+        // A trap run at the prompt may use the shell, so the editor gets its own copy
+        let history = shell.history.clone();
+        // Jobs are reported and traps run while the prompt waits for input
+        let on_wake = || shell.bg_manager.child_changed(notify) | run_pending_traps(&mut shell);
+        match get_input(&history, on_wake) {
             Ok(input) => {
-                let mut tokens = get_tokens(&input);
-
-                if tokens.items.is_empty() {
-                    continue;
-                }
-
-                // Check for background '&'
-                let mut background = false;
-                if tokens.items.last().map(|t| t.as_str()) == Some("&") {
-                    tokens.items.pop();
-                    background = true;
-                }
-
-                // Expand tilde and environment variables
-                for i in 0..tokens.items.len() {
-                    let mut exp = tokens.items[i].clone();
-                    exp = expand_tilde(&exp);
-                    exp = get_env(&exp);
-                    tokens.items[i] = exp;
-                }
-
-                // Check for built-in commands first
-                if let Some(command) = tokens.items.first() {
-                    let args = &tokens.items[1..];
-                    match execute_builtin(command, args, &history, &mut bg_manager) {
-                        Ok(true) => {
-                            // Exit command was executed
-                            return Ok(());
-                        }
-                        Ok(false) => {
-                            // Built-in command executed successfully, add to history and continue
-                            history.push(input.trim().to_string());
-                            continue;
-                        }
-                        Err(_) => {
-                            // Not a built-in command, continue to external command handling
-                        }
-                    }
-                }
-
-
-                // Split commands by '|'
-
-                let mut pipeline: Vec<TokenList> = Vec::new();
-                let mut current_cmd = TokenList { items: Vec::new() };
-
-                for t in tokens.items {
-                    if t == "|" {
-
-                        if current_cmd.items.is_empty() {
-                            eprintln!("Error: pipeline syntax error");
-                            continue;
-                        }
-
-                        pipeline.push(current_cmd);
-                        current_cmd = TokenList { items: Vec::new() };
-                    } else {
-                        current_cmd.items.push(t);
-                    }
-                }
-                pipeline.push(current_cmd);
-
-
-                // Handle pipeline
-                if pipeline.len() > 1 {
-                    // Check if the last command is empty (e.g., 'ls | ')
-                    if pipeline.last().unwrap().items.is_empty() {
-                         eprintln!("Error: pipeline syntax error ");
-                         continue;
-                    }
-
-                    // Search PATH for each command in the pipeline
-                    let mut all_found = true;
-                    for cmd in &mut pipeline {
-                        if !cmd.items.is_empty() && !cmd.items[0].contains('/') {
-                            if let Some(full_path) = search_path(&cmd.items[0]) {
-                                cmd.items[0] = full_path;
-                            } else {
-                                eprintln!("{}: command not found", cmd.items[0]);
-                                all_found = false;
-                                break;
-                            }
-                        }
+                let tokens = match get_tokens(&input) {
+                    Ok(tokens) => tokens,
+                    Err(err) => {
+                        eprintln!("Error: {}", err);
+                        continue;
                     }
+                };
 
-                    if !all_found {
+                // Build the command AST from the tokens
+                let mut list = match parse(&tokens.items) {
+                    Ok(list) => list,
+                    Err(err) => {
+                        eprintln!("Error: {}", err);
                         continue;
                     }
+                };
 
-                    if let Err(err) = execute_pipeline(&pipeline, background, &mut bg_manager) {
-                        eprintln!("{}", err);
+                // Here-document bodies follow on the next lines of input
+                if let Err(err) = read_here_docs(&mut list, get_continuation_line) {
+                    if err.kind() == io::ErrorKind::Interrupted {
+                        println!();
+                        shell.last_status = 130;
                     } else {
-                        // Add to history only for successful pipeline commands
-                        history.push(input.trim().to_string());
+                        eprintln!("Error: {}", err);
                     }
                     continue;
                 }
 
-
-                // I/O redirection for single command
-
-                let tokens = &pipeline[0];
-                let (mut cmd_tokens, input_file, output_file, parse_ok) = parse_redirection(&tokens.items);
-
-                if !parse_ok || cmd_tokens.is_empty() {
-                    if cmd_tokens.is_empty() && parse_ok {
-                         eprintln!("Error: no command specified");
-                    }
+                if list.items.is_empty() {
                     continue;
                 }
 
-                // Search PATH if no slash
+                let all_ok = execute_list(&list, &mut shell);
 
-                if !cmd_tokens[0].contains('/') {
-                    if let Some(full_path) = search_path(&cmd_tokens[0]) {
-                        cmd_tokens[0] = full_path;
-                    } else {
-                        eprintln!("{}: command not found", cmd_tokens[0]);
-                        continue;
-                    }
+                if shell.exit_requested {
+                    break;
                 }
 
-
-                // Execute (foreground or background)
-                if let Err(err) = execute_command(&cmd_tokens, input_file.as_deref(), output_file.as_deref(), background, &mut bg_manager) {
-                    eprintln!("{}", err);
-                } else {
-                    // Add to history only for successful external commands
-                    history.push(input.trim().to_string());
+                // Add to history only if every command could be run
+                if all_ok {
+                    shell.history.push(input.trim().to_string());
                 }
-
+            }
+            // Ctrl-C discards the line being typed and starts a fresh prompt
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+                println!();
+                shell.last_status = 130;
             }
             Err(err) => {
                 eprintln!("\n{}", err);
+                if !shell.may_leave() {
+                    continue;
+                }
+                run_exit_trap(&mut shell);
                 break;
             }
         }
     }
-    Ok(())
+
+    shell.bg_manager.hang_up_stopped(shell.job_control);
+
+    // The shell exits with the status of the last command it ran
+    process::exit(shell.last_status);
 }
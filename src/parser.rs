@@ -0,0 +1,401 @@
+use std::fmt;
+
+use crate::lexer::{Operator, Quote, Token, Word, WordPart};
+
+/// A `NAME=value` word written before the command name
+#[derive(Debug, Clone)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Word,
+}
+
+/// A single redirection such as `< in.txt` or `2> err.log`
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    /// File descriptor written before the operator, if any (`2` in `2>`)
+    pub fd: Option<i32>,
+    pub op: Operator,
+    pub target: Word,
+    /// Body of a `<<` or `<<-` here-document, read after the command line was parsed
+    pub here_doc: Option<Word>,
+}
+
+/// A command with its arguments, redirections and prefix assignments
+#[derive(Debug, Clone, Default)]
+pub struct SimpleCommand {
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// Commands joined by `|`
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    pub commands: Vec<SimpleCommand>,
+}
+
+/// How a pipeline is chained onto the previous one in an and/or list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connector {
+    And, // &&
+    Or,  // ||
+}
+
+/// Pipelines joined by `&&` and `||`
+#[derive(Debug, Clone)]
+pub struct AndOrList {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+}
+
+/// One entry of a command list, terminated by `;`, `&` or end of input
+#[derive(Debug, Clone)]
+pub struct ListItem {
+    pub and_or: AndOrList,
+    pub background: bool,
+}
+
+/// A full command line: and/or lists separated by `;` or `&`
+#[derive(Debug, Clone, Default)]
+pub struct CommandList {
+    pub items: Vec<ListItem>,
+}
+
+/// Parse a token stream into a command list
+pub fn parse(tokens: &[Token]) -> Result<CommandList, String> {
+    let mut parser = Parser { tokens, pos: 0 };
+    let list = parser.parse_list()?;
+    match parser.peek() {
+        None => Ok(list),
+        Some(token) => Err(unexpected(Some(token))),
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn peek_op(&self) -> Option<Operator> {
+        match self.peek() {
+            Some(Token::Op(op)) => Some(*op),
+            _ => None,
+        }
+    }
+
+    /// list := and_or ((';' | '&') and_or)* [';' | '&']
+    fn parse_list(&mut self) -> Result<CommandList, String> {
+        let mut list = CommandList::default();
+
+        while self.peek().is_some() {
+            let and_or = self.parse_and_or()?;
+            let background = match self.peek_op() {
+                Some(Operator::Semi) => {
+                    self.pos += 1;
+                    false
+                }
+                Some(Operator::Amp) => {
+                    self.pos += 1;
+                    true
+                }
+                None if self.peek().is_none() => false,
+                _ => return Err(unexpected(self.peek())),
+            };
+            list.items.push(ListItem { and_or, background });
+        }
+
+        Ok(list)
+    }
+
+    /// and_or := pipeline (('&&' | '||') pipeline)*
+    fn parse_and_or(&mut self) -> Result<AndOrList, String> {
+        let first = self.parse_pipeline()?;
+        let mut rest = Vec::new();
+
+        loop {
+            let connector = match self.peek_op() {
+                Some(Operator::AndIf) => Connector::And,
+                Some(Operator::OrIf) => Connector::Or,
+                _ => break,
+            };
+            self.pos += 1;
+            rest.push((connector, self.parse_pipeline()?));
+        }
+
+        Ok(AndOrList { first, rest })
+    }
+
+    /// pipeline := command ('|' command)*
+    fn parse_pipeline(&mut self) -> Result<Pipeline, String> {
+        let mut commands = vec![self.parse_command()?];
+
+        while self.peek_op() == Some(Operator::Pipe) {
+            self.pos += 1;
+            commands.push(self.parse_command()?);
+        }
+
+        Ok(Pipeline { commands })
+    }
+
+    /// command := (assignment | word | redirect)+
+    fn parse_command(&mut self) -> Result<SimpleCommand, String> {
+        let mut cmd = SimpleCommand::default();
+
+        loop {
+            match self.peek() {
+                Some(Token::Word(word)) => {
+                    self.pos += 1;
+                    match split_assignment(word) {
+                        Some(assignment) if cmd.words.is_empty() => {
+                            cmd.assignments.push(assignment)
+                        }
+                        _ => cmd.words.push(word.clone()),
+                    }
+                }
+                Some(Token::IoNumber(fd)) => {
+                    self.pos += 1;
+                    match self.next() {
+                        Some(Token::Op(op)) if op.is_redirection() => {
+                            let target = self.parse_redirect_target()?;
+                            cmd.redirects.push(Redirect { fd: Some(*fd), op: *op, target, here_doc: None });
+                        }
+                        other => return Err(unexpected(other)),
+                    }
+                }
+                Some(Token::Op(op)) if op.is_redirection() => {
+                    self.pos += 1;
+                    let target = self.parse_redirect_target()?;
+                    cmd.redirects.push(Redirect { fd: None, op: *op, target, here_doc: None });
+                }
+                _ => break,
+            }
+        }
+
+        if cmd.assignments.is_empty() && cmd.words.is_empty() && cmd.redirects.is_empty() {
+            return Err(unexpected(self.peek()));
+        }
+
+        Ok(cmd)
+    }
+
+    fn parse_redirect_target(&mut self) -> Result<Word, String> {
+        match self.next() {
+            Some(Token::Word(word)) => Ok(word.clone()),
+            other => Err(unexpected(other)),
+        }
+    }
+}
+
+/// Build the bash-style message for a token that cannot appear where it was found
+fn unexpected(token: Option<&Token>) -> String {
+    let text = match token {
+        None => "newline".to_string(),
+        Some(Token::Op(op)) => op.as_str().to_string(),
+        Some(Token::IoNumber(fd)) => fd.to_string(),
+        Some(Token::Word(word)) => word.text(),
+    };
+    format!("syntax error near unexpected token `{}'", text)
+}
+
+/// Split `NAME=value` into an assignment if the name part is unquoted and valid
+fn split_assignment(word: &Word) -> Option<Assignment> {
+    let first = word.parts.first()?;
+    if first.quote != Quote::None {
+        return None;
+    }
+    let (name, value) = first.text.split_once('=')?;
+    if !is_valid_name(name) {
+        return None;
+    }
+
+    let mut parts = vec![WordPart {
+        text: value.to_string(),
+        quote: Quote::None,
+        expanded: false,
+    }];
+    parts.extend(word.parts[1..].iter().cloned());
+    Some(Assignment {
+        name: name.to_string(),
+        value: Word { parts },
+    })
+}
+
+/// True for identifiers usable as variable names: letters, digits and `_`, not starting with a digit
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl fmt::Display for SimpleCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts: Vec<String> = Vec::new();
+        for a in &self.assignments {
+            parts.push(format!("{}={}", a.name, a.value.text()));
+        }
+        for w in &self.words {
+            parts.push(w.text());
+        }
+        for r in &self.redirects {
+            let fd = r.fd.map(|fd| fd.to_string()).unwrap_or_default();
+            parts.push(format!("{}{} {}", fd, r.op.as_str(), r.target.text()));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let commands: Vec<String> = self.commands.iter().map(|c| c.to_string()).collect();
+        write!(f, "{}", commands.join(" | "))
+    }
+}
+
+impl fmt::Display for AndOrList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.first)?;
+        for (connector, pipeline) in &self.rest {
+            let op = match connector {
+                Connector::And => "&&",
+                Connector::Or => "||",
+            };
+            write!(f, " {} {}", op, pipeline)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::get_tokens;
+
+    fn parse_str(input: &str) -> Result<CommandList, String> {
+        parse(&get_tokens(input)?.items)
+    }
+
+    fn words(cmd: &SimpleCommand) -> Vec<String> {
+        cmd.words.iter().map(Word::text).collect()
+    }
+
+    #[test]
+    fn pipes_bind_tighter_than_and_or() {
+        let list = parse_str("a | b && c || d | e").unwrap();
+        assert_eq!(list.items.len(), 1);
+        let and_or = &list.items[0].and_or;
+        assert_eq!(and_or.first.commands.len(), 2);
+        let connectors: Vec<Connector> = and_or.rest.iter().map(|(c, _)| *c).collect();
+        assert_eq!(connectors, vec![Connector::And, Connector::Or]);
+        assert_eq!(and_or.rest[1].1.commands.len(), 2);
+        assert_eq!(and_or.to_string(), "a | b && c || d | e");
+    }
+
+    #[test]
+    fn semicolon_and_ampersand_separate_and_or_lists() {
+        let list = parse_str("a && b & c; d || e &").unwrap();
+        let items: Vec<(String, bool)> = list
+            .items
+            .iter()
+            .map(|item| (item.and_or.to_string(), item.background))
+            .collect();
+        assert_eq!(
+            items,
+            vec![
+                ("a && b".to_string(), true),
+                ("c".to_string(), false),
+                ("d || e".to_string(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn trailing_semicolon_is_allowed() {
+        let list = parse_str("a;").unwrap();
+        assert_eq!(list.items.len(), 1);
+        assert!(!list.items[0].background);
+    }
+
+    #[test]
+    fn empty_input_is_an_empty_list() {
+        assert!(parse_str("").unwrap().items.is_empty());
+    }
+
+    #[test]
+    fn leading_name_value_words_are_assignments() {
+        let list = parse_str("A=1 _b=\"x y\" cmd C=2").unwrap();
+        let cmd = &list.items[0].and_or.first.commands[0];
+        let assignments: Vec<(String, String)> = cmd
+            .assignments
+            .iter()
+            .map(|a| (a.name.clone(), a.value.text()))
+            .collect();
+        assert_eq!(
+            assignments,
+            vec![("A".to_string(), "1".to_string()), ("_b".to_string(), "x y".to_string())]
+        );
+        assert_eq!(words(cmd), vec!["cmd", "C=2"]);
+    }
+
+    #[test]
+    fn invalid_or_quoted_names_are_not_assignments() {
+        for input in ["1A=x", "'A'=x", "A-B=x", "=x"] {
+            let list = parse_str(input).unwrap();
+            let cmd = &list.items[0].and_or.first.commands[0];
+            assert!(cmd.assignments.is_empty(), "{}", input);
+            assert_eq!(cmd.words.len(), 1, "{}", input);
+        }
+    }
+
+    #[test]
+    fn redirections_may_appear_anywhere_in_a_command() {
+        let list = parse_str(">out echo 2>err hi <in").unwrap();
+        let cmd = &list.items[0].and_or.first.commands[0];
+        assert_eq!(words(cmd), vec!["echo", "hi"]);
+        let redirects: Vec<(Option<i32>, Operator, String)> = cmd
+            .redirects
+            .iter()
+            .map(|r| (r.fd, r.op, r.target.text()))
+            .collect();
+        assert_eq!(
+            redirects,
+            vec![
+                (None, Operator::Great, "out".to_string()),
+                (Some(2), Operator::Great, "err".to_string()),
+                (None, Operator::Less, "in".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn is_valid_name_rules() {
+        assert!(is_valid_name("a"));
+        assert!(is_valid_name("_"));
+        assert!(is_valid_name("A_1"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("1a"));
+        assert!(!is_valid_name("a-b"));
+    }
+
+    #[test]
+    fn syntax_errors_name_the_unexpected_token() {
+        let error = |input| parse_str(input).unwrap_err();
+        assert_eq!(error("| a"), "syntax error near unexpected token `|'");
+        assert_eq!(error("a &&"), "syntax error near unexpected token `newline'");
+        assert_eq!(error("a ;; b"), "syntax error near unexpected token `;'");
+        assert_eq!(error("a | | b"), "syntax error near unexpected token `|'");
+        assert_eq!(error("echo >"), "syntax error near unexpected token `newline'");
+        assert_eq!(error("echo > ;"), "syntax error near unexpected token `;'");
+        assert_eq!(error("& a"), "syntax error near unexpected token `&'");
+    }
+}
@@ -1,16 +1,13 @@
-use std::env;
 use std::fs;
 use std::path::Path;
 
-pub fn search_path(command: &str) -> Option<String> {
+/// Search each directory of the shell's PATH value for an executable named `command`
+pub fn search_path(command: &str, path_var: Option<&str>) -> Option<String> {
     if command.contains('/') {
         return None;
     }
 
-    let path_var = match env::var("PATH") {
-        Ok(path) => path,
-        Err(_) => return None,
-    };
+    let path_var = path_var?;
 
     for directory in path_var.split(':') {
         let directory = if directory.is_empty() { "." } else { directory };
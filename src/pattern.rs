@@ -0,0 +1,164 @@
+use crate::lexer::{Quote, Word};
+
+/// Match `text` against a shell pattern supporting `*`, `?`, `[...]` (with `!`/`^`
+/// negation and ranges) and backslash-escaped literal characters
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    match_from(&pattern, &text)
+}
+
+fn match_from(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Position to retry from after the most recent '*': (pattern index, text index)
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match match_class(&pattern[p..], text[t]) {
+                Some((true, len)) => Some(len),
+                Some((false, _)) => None,
+                // An unterminated '[' is an ordinary character
+                None => (text[t] == '[').then_some(1),
+            },
+            Some('\\') if p + 1 < pattern.len() => (pattern[p + 1] == text[t]).then_some(2),
+            Some(&c) => (c == text[t]).then_some(1),
+            None => None,
+        };
+
+        match step {
+            Some(len) => {
+                p += len;
+                t += 1;
+            }
+            None => match backtrack {
+                // Let the last '*' swallow one more character and try again
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    backtrack = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Match one character against a bracket expression at the start of `pattern`.
+/// Returns (matched, length of the bracket expression), or None if it is unterminated.
+fn match_class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = matches!(pattern.get(i), Some('!' | '^'));
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    while i < pattern.len() {
+        let mut lo = pattern[i];
+        if lo == ']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+        if lo == '\\' && i + 1 < pattern.len() {
+            i += 1;
+            lo = pattern[i];
+        }
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&hi| hi != ']') {
+            let hi = pattern[i + 2];
+            matched |= lo <= c && c <= hi;
+            i += 3;
+        } else {
+            matched |= lo == c;
+            i += 1;
+        }
+    }
+
+    None
+}
+
+/// Escape every pattern character so the text only matches itself
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Turn an expanded word into a pattern, where quoted parts only match literally
+pub fn word_to_pattern(word: &Word) -> String {
+    word.parts
+        .iter()
+        .map(|part| match part.quote {
+            Quote::None => part.text.clone(),
+            Quote::Single | Quote::Double => escape(&part.text),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::WordPart;
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*", ""));
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "main.rc"));
+        assert!(matches("a*b*c", "aXXbYYbc"));
+        assert!(matches("?", "é"));
+        assert!(!matches("??", "a"));
+    }
+
+    #[test]
+    fn bracket_expressions() {
+        assert!(matches("[abc]", "b"));
+        assert!(matches("[a-c]x", "cx"));
+        assert!(!matches("[a-c]", "d"));
+        assert!(matches("[!a-c]", "d"));
+        assert!(!matches("[^a-c]", "a"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[a-]", "-"));
+    }
+
+    #[test]
+    fn unterminated_bracket_is_literal() {
+        assert!(matches("[a", "[a"));
+        assert!(!matches("[a", "a"));
+    }
+
+    #[test]
+    fn escapes_match_literally() {
+        assert!(matches(r"\*", "*"));
+        assert!(!matches(r"\*", "x"));
+        assert!(matches(&escape("a*[b]?"), "a*[b]?"));
+        assert!(!matches(&escape("a*"), "ab"));
+    }
+
+    #[test]
+    fn quoted_parts_of_a_word_only_match_themselves() {
+        let word = Word {
+            parts: vec![
+                WordPart { text: "*".to_string(), quote: Quote::Double, expanded: false },
+                WordPart { text: "*".to_string(), quote: Quote::None, expanded: false },
+            ],
+        };
+        let pattern = word_to_pattern(&word);
+        assert!(matches(&pattern, "*abc"));
+        assert!(!matches(&pattern, "abc"));
+    }
+}
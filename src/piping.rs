@@ -1,96 +1,185 @@
-use crate::background::BackgroundManager;
-use crate::lexer::TokenList;
+use crate::builtin::{execute_builtin, is_builtin};
+use crate::background::Job;
+use crate::job_control::{place_in_job, run_foreground};
+use crate::redirection::{Redirection, execute_execv, redirect_io};
+use crate::shell::{Shell, exit_code};
+use crate::signals::reset_child_signals;
+use nix::errno::Errno;
 use nix::sys::wait::waitpid;
 use nix::unistd::Pid;
-use nix::unistd::{ForkResult, close, dup2, execv, fork, pipe};
-use std::ffi::CString;
-use std::io;
+use nix::unistd::{ForkResult, close, dup2, fork, getpid, pipe};
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{FromRawFd, RawFd};
 
+/// Run commands connected by pipes. Each pipe is created just before the stage that
+/// writes to it, so a child only ever holds its own stdin/stdout ends and the number
+/// of open descriptors stays constant however long the pipeline is.
+/// Each stage's own redirections are applied after its pipe ends are in place, so
+/// an explicit redirection wins over the pipe.
+/// Builtin stages run in their forked child like any other command, so their output
+/// can be piped. A stage with no command words only applies its redirections and
+/// succeeds. `command_line` is the pipeline as written, which names a background
+/// job. A foreground pipeline is waited for with `run_foreground`, which also
+/// records any background job that changes state in the meantime. Returns the exit
+/// status of every stage in order, or nothing for a background job.
 pub fn execute_pipeline(
-    commands: &[TokenList],
+    commands: &[Vec<String>],
+    command_line: &str,
+    envs: &[Vec<CString>],
+    redirects: &[Vec<Redirection>],
     background: bool,
-    bg_manager: &mut BackgroundManager,
-    ) -> io::Result<()> {
+    shell: &mut Shell,
+    ) -> io::Result<Vec<i32>> {
     if commands.is_empty() {
-        return Ok(());
+        return Ok(Vec::new());
     }
-    if commands.len() > 3 {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "Only accept up to 2 pipes",
-        ));
-    }
-
-    let mut pipes = Vec::new();
 
-    // Create pipes
-    for _ in 0..commands.len() - 1 {
-        pipes.push(pipe().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?);
-    }
+    // Checked before anything is forked, so a bad argument leaves no stage behind
+    let argvs: Vec<Vec<CString>> = commands
+        .iter()
+        .map(|cmd| {
+            cmd.iter()
+                .map(|s| CString::new(s.as_str()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e)))
+                .collect::<Result<_, _>>()
+        })
+        .collect::<Result<_, _>>()?;
 
-    let mut last_pid: Option<Pid> = None;
+    let mut pids: Vec<Pid> = Vec::new();
+    // The first stage leads the process group that every later stage joins
+    let mut pgid: Option<Pid> = None;
+    // Read end of the pipe from the previous stage, which becomes this stage's stdin
+    let mut prev_read: Option<RawFd> = None;
 
     for (i, cmd) in commands.iter().enumerate() {
-        if cmd.items.is_empty() {
-            continue;
-        }
-
-        let cstrings: Vec<CString> = cmd
-            .items
-            .iter()
-            .map(|s| CString::new(s.as_str()).unwrap())
-            .collect();
+        let next_pipe = if i < commands.len() - 1 {
+            match pipe() {
+                Ok(fds) => Some(fds),
+                Err(e) => {
+                    if let Some(r) = prev_read {
+                        let _ = close(r);
+                    }
+                    return Err(io::Error::other(e));
+                }
+            }
+        } else {
+            None
+        };
 
-        match unsafe { fork().map_err(|e| io::Error::new(io::ErrorKind::Other, e))? } {
-            ForkResult::Child => {
+        let cstrings = &argvs[i];
 
-                if i > 0 {
-                    let (read_end, _) = pipes[i - 1];
-                    dup2(read_end, 0).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => {
+                reset_child_signals();
+                if shell.job_control {
+                    place_in_job(getpid(), pgid, !background);
                 }
-                if i < commands.len() - 1 {
-                    let (_, write_end) = pipes[i];
-                    dup2(write_end, 1).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+                // Errors must end the child here, never return into a copy of the shell
+                if let Some(read_end) = prev_read {
+                    if dup2(read_end, 0).is_err() {
+                        std::process::exit(1);
+                    }
+                    let _ = close(read_end);
+                }
+                if let Some((read_end, write_end)) = next_pipe {
+                    if dup2(write_end, 1).is_err() {
+                        std::process::exit(1);
+                    }
+                    let _ = close(read_end);
+                    let _ = close(write_end);
+                }
+                if let Err(e) = redirect_io(&redirects[i]) {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
                 }
 
-
-                for &(r, w) in &pipes {
-                    let _ = close(r);
-                    let _ = close(w);
+                // A stage of only redirections, or whose words expanded to nothing,
+                // has applied its redirections and runs no command
+                let Some(name) = cmd.first() else {
+                    std::process::exit(0);
+                };
+                if is_builtin(name) {
+                    shell.enter_subshell();
+                    let status = execute_builtin(name, &cmd[1..], shell).unwrap_or(1);
+                    let _ = io::stdout().flush();
+                    std::process::exit(status);
                 }
 
-                execv(&cstrings[0], &cstrings)
-                    .map_err(|_| io::Error::new(io::ErrorKind::Other, "execv failed"))?;
-                std::process::exit(1);
+                let args: Vec<&CStr> = cstrings.iter().map(|s| s.as_ref()).collect();
+                execute_execv(&cstrings[0], &args, &envs[i]);
+            }
+            Ok(ForkResult::Parent { child }) => {
+                if shell.job_control {
+                    place_in_job(child, pgid, !background);
+                }
+                pgid.get_or_insert(child);
+                pids.push(child);
             }
-            ForkResult::Parent { child } => {
-                last_pid = Some(child);
+            Err(e) => {
+                for fd in prev_read.into_iter().chain(next_pipe.into_iter().flat_map(|(r, w)| [r, w])) {
+                    let _ = close(fd);
+                }
+                return Err(io::Error::other(format!("Fork failed: {}", e)));
             }
         }
-    }
 
-    // Close pipes in parent
-    for &(r, w) in &pipes {
-        let _ = close(r);
-        let _ = close(w);
+        // The parent keeps only the read end that feeds the next stage
+        if let Some(r) = prev_read {
+            let _ = close(r);
+        }
+        prev_read = next_pipe.map(|(read_end, write_end)| {
+            let _ = close(write_end);
+            read_end
+        });
     }
 
+    let pgid = pgid.unwrap_or(pids[0]);
+    let raw_pids = pids.iter().map(|pid| pid.as_raw()).collect();
     if background {
-        if let Some(pid) = last_pid {
-            let mut all_cmds: Vec<String> = Vec::new();
-            for c in commands {
-                all_cmds.extend(c.items.clone());
-                all_cmds.push("|".to_string());
+        shell.bg_manager.add_job(pgid.as_raw(), raw_pids, command_line.to_string());
+        Ok(Vec::new())
+    } else {
+        // Foreground: wait until every stage has ended or the job stopped. Background
+        // jobs that change state meanwhile are reaped too and their results handed to
+        // the BackgroundManager, which reports them.
+        let job = Job::new(pgid.as_raw(), raw_pids, command_line.to_string());
+        Ok(run_foreground(job, shell))
+    }
+}
+
+/// Fork a child that runs `run` with its stdout connected to a pipe, and collect
+/// everything it writes. Returns the output and the child's exit status.
+pub fn capture_output<F: FnOnce() -> i32>(run: F) -> io::Result<(String, i32)> {
+    let (read_end, write_end) = pipe().map_err(io::Error::other)?;
+
+    match unsafe { fork().map_err(io::Error::other)? } {
+        ForkResult::Child => {
+            reset_child_signals();
+            let _ = close(read_end);
+            if dup2(write_end, 1).is_err() {
+                std::process::exit(1);
             }
-            all_cmds.pop(); // remove trailing "|"
-            bg_manager.add_job(pid.as_raw(), all_cmds.join(" "));
+            let _ = close(write_end);
+            let status = run();
+            let _ = io::stdout().flush();
+            std::process::exit(status);
         }
-        Ok(())
-    } else {
-        // Foreground: wait for all
-        for _ in 0..commands.len() {
-            let _ = waitpid(Pid::from_raw(-1), None);
+        ForkResult::Parent { child } => {
+            let _ = close(write_end);
+            let mut output = Vec::new();
+            // SAFETY: read_end is a fresh pipe fd owned only by this File from here on
+            let mut reader = unsafe { File::from_raw_fd(read_end) };
+            reader.read_to_end(&mut output)?;
+            // A shell word cannot hold NUL bytes, so drop them as bash does
+            output.retain(|&byte| byte != 0);
+            let status = loop {
+                match waitpid(child, None) {
+                    Err(Errno::EINTR) => continue,
+                    result => break result.map(exit_code).unwrap_or(1),
+                }
+            };
+            Ok((String::from_utf8_lossy(&output).into_owned(), status))
         }
-        Ok(())
     }
 }
@@ -1,59 +1,24 @@
 use nix::errno::Errno;
-use nix::sys::wait::waitpid;
-use nix::unistd::{ForkResult, dup2, execv, fork};
+use nix::fcntl::{FcntlArg, fcntl};
+use nix::unistd::{ForkResult, close, dup2, execve, fork, getpid};
 use std::ffi::{CStr, CString};
-use std::fs::OpenOptions;
-use std::io;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
 use std::os::unix::fs::OpenOptionsExt;
-use std::os::unix::io::AsRawFd;
+use std::os::unix::io::{AsRawFd, IntoRawFd, RawFd};
 use std::process;
 
-// Import the manager and Job structure
-use crate::background::BackgroundManager;
-
-/// Parse I/O redirection tokens from a command
-/// Returns (cmd_tokens, input_file, output_file, parse_ok)
-pub fn parse_redirection(tokens: &Vec<String>) -> (Vec<String>, Option<String>, Option<String>, bool) {
-    let mut cmd_tokens = Vec::new();
-    let mut input_file: Option<String> = None;
-    let mut output_file: Option<String> = None;
-    let mut parse_ok = true;
-
-    let mut i = 0;
-    while i < tokens.len() {
-        match tokens[i].as_str() {
-            "<" => {
-                if i + 1 < tokens.len() {
-                    input_file = Some(tokens[i + 1].clone());
-                    i += 1;
-                } else {
-                    eprintln!("Error: no input file specified after '<'");
-                    parse_ok = false;
-                    break;
-                }
-            }
-            ">" => {
-                if i + 1 < tokens.len() {
-                    output_file = Some(tokens[i + 1].clone());
-                    i += 1;
-                } else {
-                    eprintln!("Error: no output file specified after '>'");
-                    parse_ok = false;
-                    break;
-                }
-            }
-            _ => cmd_tokens.push(tokens[i].clone()),
-        }
-        i += 1;
-    }
-
-    (cmd_tokens, input_file, output_file, parse_ok)
-}
+use crate::background::Job;
+use crate::job_control::{place_in_job, run_foreground};
+use crate::lexer::Operator;
+use crate::shell::Shell;
+use crate::signals::reset_child_signals;
 
-/// Helper function to execute execv. It should return ! (never) on success.
-fn execute_execv(path: &CString, args: &[&CStr]) -> ! {
-    match execv(path, args) {
-        Ok(_) => unreachable!(), // execv success means we never reach here
+/// Helper function to execute execve with the given environment. It should return ! (never) on success.
+pub fn execute_execv(path: &CString, args: &[&CStr], env: &[CString]) -> ! {
+    match execve(path, args, env) {
+        Ok(_) => unreachable!(), // execve success means we never reach here
         Err(e) => {
             // Handle common execv errors
             let exit_code = match e {
@@ -67,14 +32,17 @@ fn execute_execv(path: &CString, args: &[&CStr]) -> ! {
     }
 }
 
-/// Execute a single command, possibly with I/O redirection or in background
+/// Execute a single command, possibly with I/O redirection or in background.
+/// `command_line` is the command as written, which names a background job.
+/// Returns the exit status of a foreground command, or 0 once a background job is started
 pub fn execute_command(
-    cmd_tokens: &Vec<String>,
-    input_file: Option<&str>,
-    output_file: Option<&str>,
+    cmd_tokens: &[String],
+    command_line: &str,
+    env: &[CString],
+    redirects: &[Redirection],
     background: bool,
-    manager: &mut BackgroundManager,
-) -> io::Result<()> {
+    shell: &mut Shell,
+) -> io::Result<i32> {
     if cmd_tokens.is_empty() {
         return Err(io::Error::new(
             io::ErrorKind::InvalidInput,
@@ -85,7 +53,6 @@ pub fn execute_command(
 
     // The background flag is passed in via the 'background' parameter.
     let tokens: Vec<String> = cmd_tokens.to_vec();
-    let command_line_string = tokens.join(" "); // Store command for job report
 
     // 2. Convert command and arguments to CString
     let cmd_cstring = CString::new(tokens[0].clone())
@@ -103,53 +70,304 @@ pub fn execute_command(
     // 3. Fork Process
     match unsafe { fork() } {
         Ok(ForkResult::Child) => {
-            // --- Child Process Setup (I/O Redirection) ---
-
-            // Input redirection
-            if let Some(input_path) = input_file {
-                let input = OpenOptions::new()
-                    .read(true)
-                    .open(input_path)
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-                // Must use dup2 in child process
-                dup2(input.as_raw_fd(), 0).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            // --- Child Process Setup (Signals, Process Group and I/O Redirection) ---
+            reset_child_signals();
+            if shell.job_control {
+                place_in_job(getpid(), None, !background);
             }
-
-            // Output redirection
-            if let Some(output_path) = output_file {
-                let output = OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .truncate(true)
-                    .mode(0o600) // -rw-------
-                    .open(output_path)
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-                // Must use dup2 in child process
-                dup2(output.as_raw_fd(), 1).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            // Errors must end the child here, never return into a copy of the shell
+            if let Err(e) = redirect_io(redirects) {
+                eprintln!("{}", e);
+                process::exit(1);
             }
 
             // Execute the command (uses the helper to handle errors)
-            execute_execv(&cmd_cstring, &args_ref);
+            execute_execv(&cmd_cstring, &args_ref, env);
         }
 
         Ok(ForkResult::Parent { child }) => {
             // --- Parent Process Logic ---
+            if shell.job_control {
+                place_in_job(child, None, !background);
+            }
             // Use the passed-in 'background' bool
             if background {
-                // Background: Register the job with the manager (pid is i32, as expected)
-                manager.add_job(child.as_raw(), command_line_string);
-                Ok(())
+                // Background: Register the job with the manager; the command leads its own group
+                shell.bg_manager.add_job(child.as_raw(), vec![child.as_raw()], command_line.to_string());
+                Ok(0)
             } else {
-                // Foreground: Wait for child to finish
-                match waitpid(child, None) {
-                    Ok(_) => Ok(()),
-                    Err(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
+                // Foreground: Wait for child to finish or stop, with the terminal in its hands
+                let job = Job::new(child.as_raw(), vec![child.as_raw()], command_line.to_string());
+                Ok(run_foreground(job, shell)[0])
+            }
+        }
+        Err(e) => Err(io::Error::other(format!("Fork failed: {}", e))),
+    }
+}
+
+/// Copies of file descriptors taken before they were redirected, so a builtin run
+/// inside the shell process can have its original I/O put back afterwards
+#[derive(Default)]
+pub struct SavedFds {
+    /// (redirected fd, copy of the original or None if it was not open)
+    saved: Vec<(RawFd, Option<RawFd>)>,
+}
+
+impl SavedFds {
+    /// Remember the current target of `fd`, once, before it is first replaced
+    fn save(&mut self, fd: RawFd) {
+        if self.saved.iter().any(|(saved_fd, _)| *saved_fd == fd) {
+            return;
+        }
+        // Keep the copy above the low fds and out of exec'd children
+        let copy = fcntl(fd, FcntlArg::F_DUPFD_CLOEXEC(10)).ok();
+        self.saved.push((fd, copy));
+    }
+
+    /// Put every saved descriptor back in place
+    pub fn restore(self) {
+        // Anything still buffered belongs to the redirected target
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        for (fd, copy) in self.saved.into_iter().rev() {
+            match copy {
+                Some(copy) => {
+                    let _ = dup2(copy, fd);
+                    let _ = close(copy);
+                }
+                None => {
+                    let _ = close(fd);
                 }
             }
         }
-        Err(e) => Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("Fork failed: {}", e),
-        )),
+    }
+}
+
+/// How a redirection opens its file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileMode {
+    Read,      // <
+    Write,     // >, >|
+    NoClobber, // > under `set -o noclobber`: never replaces an existing regular file
+    Append,    // >>
+    ReadWrite, // <>
+}
+
+/// A redirection with its target expanded, ready to be applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Redirection {
+    /// Open `path` as `fd`
+    File { fd: RawFd, path: String, mode: FileMode },
+    /// Make `fd` a copy of `source`, as in `2>&1`
+    Dup { fd: RawFd, source: RawFd },
+    /// Close `fd`, as in `2>&-`
+    Close { fd: RawFd },
+    /// Feed `text` to `fd`, for here-documents and here-strings
+    Here { fd: RawFd, text: String },
+}
+
+/// Turn a parsed redirection operator, its optional fd and its expanded target (the
+/// text itself for here-documents and here-strings) into the steps that carry it out.
+/// `&>` and `>&file` become two steps. With `noclobber`, every truncating form except
+/// `>|` refuses to overwrite an existing file.
+pub fn resolve_redirect(
+    op: Operator,
+    fd: Option<i32>,
+    target: String,
+    noclobber: bool,
+) -> Result<Vec<Redirection>, String> {
+    let file = |fd: RawFd, mode: FileMode, path: String| Redirection::File { fd, path, mode };
+    let write = if noclobber { FileMode::NoClobber } else { FileMode::Write };
+
+    let steps = match op {
+        Operator::Less => vec![file(fd.unwrap_or(0), FileMode::Read, target)],
+        Operator::Great => vec![file(fd.unwrap_or(1), write, target)],
+        Operator::Clobber => vec![file(fd.unwrap_or(1), FileMode::Write, target)],
+        Operator::DGreat => vec![file(fd.unwrap_or(1), FileMode::Append, target)],
+        Operator::LessGreat => vec![file(fd.unwrap_or(0), FileMode::ReadWrite, target)],
+        Operator::AndGreat => vec![file(1, write, target), Redirection::Dup { fd: 2, source: 1 }],
+        Operator::AndDGreat => vec![file(1, FileMode::Append, target), Redirection::Dup { fd: 2, source: 1 }],
+        Operator::LessAnd | Operator::GreatAnd => {
+            let default_fd = if op == Operator::LessAnd { 0 } else { 1 };
+            let fd = fd.unwrap_or(default_fd);
+            if target == "-" {
+                vec![Redirection::Close { fd }]
+            } else if let Ok(source) = target.parse::<RawFd>() {
+                vec![Redirection::Dup { fd, source }]
+            } else if op == Operator::GreatAnd && fd == 1 {
+                // `>&file` is another spelling of `&>file`
+                vec![file(1, write, target), Redirection::Dup { fd: 2, source: 1 }]
+            } else {
+                return Err(format!("{}: ambiguous redirect", target));
+            }
+        }
+        Operator::DLess | Operator::DLessDash | Operator::TLess => {
+            vec![Redirection::Here { fd: fd.unwrap_or(0), text: target }]
+        }
+        _ => return Err(format!("redirection '{}' is not supported", op.as_str())),
+    };
+    Ok(steps)
+}
+
+/// Apply redirections left to right, returning the original descriptors. A child
+/// about to exec can simply drop the result; the shell itself restores it once a
+/// builtin has finished.
+pub fn redirect_io(redirects: &[Redirection]) -> io::Result<SavedFds> {
+    let mut saved = SavedFds::default();
+    for redirect in redirects {
+        if let Err(e) = apply_redirect(redirect, &mut saved) {
+            saved.restore();
+            return Err(e);
+        }
+    }
+    Ok(saved)
+}
+
+fn apply_redirect(redirect: &Redirection, saved: &mut SavedFds) -> io::Result<()> {
+    match redirect {
+        Redirection::File { fd, path, mode } => {
+            let mut options = OpenOptions::new();
+            match mode {
+                FileMode::Read => options.read(true),
+                FileMode::Write => options.write(true).create(true).truncate(true),
+                FileMode::NoClobber => match fs::metadata(path) {
+                    Ok(meta) if meta.is_file() => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!("{}: cannot overwrite existing file", path),
+                        ));
+                    }
+                    // Devices such as /dev/null may still be written
+                    Ok(_) => options.write(true),
+                    Err(_) => options.write(true).create_new(true),
+                },
+                FileMode::Append => options.append(true).create(true),
+                FileMode::ReadWrite => options.read(true).write(true).create(true),
+            };
+            let file = options
+                .mode(0o666) // -rw-rw-rw-, less the umask
+                .open(path)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+
+            saved.save(*fd);
+            if file.as_raw_fd() == *fd {
+                // The file landed on the wanted fd already; keep it open
+                let _ = file.into_raw_fd();
+            } else {
+                dup2(file.as_raw_fd(), *fd).map_err(io::Error::other)?;
+            }
+        }
+        Redirection::Dup { fd, source } => {
+            if fcntl(*source, FcntlArg::F_GETFD).is_err() {
+                return Err(io::Error::other(format!("{}: Bad file descriptor", source)));
+            }
+            if fd != source {
+                saved.save(*fd);
+                dup2(*source, *fd).map_err(io::Error::other)?;
+            }
+        }
+        Redirection::Close { fd } => {
+            saved.save(*fd);
+            let _ = close(*fd);
+        }
+        Redirection::Here { fd, text } => {
+            let file = here_doc_file(text)?;
+            saved.save(*fd);
+            if file.as_raw_fd() == *fd {
+                let _ = file.into_raw_fd();
+            } else {
+                dup2(file.as_raw_fd(), *fd).map_err(io::Error::other)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write here-document text to an already unlinked temporary file and return it
+/// rewound for reading. Unlike a pipe, a file can hold a body of any size without
+/// a writer process.
+fn here_doc_file(text: &str) -> io::Result<File> {
+    let mut attempt = 0;
+    let mut file = loop {
+        let path = env::temp_dir().join(format!("sh-heredoc-{}-{}", process::id(), attempt));
+        match OpenOptions::new().read(true).write(true).create_new(true).mode(0o600).open(&path) {
+            Ok(file) => {
+                let _ = fs::remove_file(&path);
+                break file;
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => return Err(io::Error::new(e.kind(), format!("cannot create temp file for here-document: {}", e))),
+        }
+    };
+    file.write_all(text.as_bytes())?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(op: Operator, fd: Option<i32>, target: &str) -> Result<Vec<Redirection>, String> {
+        resolve_redirect(op, fd, target.to_string(), false)
+    }
+
+    fn file(fd: RawFd, mode: FileMode, path: &str) -> Redirection {
+        Redirection::File { fd, path: path.to_string(), mode }
+    }
+
+    #[test]
+    fn file_operators_default_to_stdin_or_stdout() {
+        assert_eq!(resolve(Operator::Less, None, "in"), Ok(vec![file(0, FileMode::Read, "in")]));
+        assert_eq!(resolve(Operator::Great, None, "out"), Ok(vec![file(1, FileMode::Write, "out")]));
+        assert_eq!(resolve(Operator::DGreat, Some(2), "log"), Ok(vec![file(2, FileMode::Append, "log")]));
+        assert_eq!(resolve(Operator::LessGreat, None, "rw"), Ok(vec![file(0, FileMode::ReadWrite, "rw")]));
+    }
+
+    #[test]
+    fn noclobber_only_spares_clobber() {
+        let resolve = |op| resolve_redirect(op, None, "f".to_string(), true);
+        assert_eq!(resolve(Operator::Great), Ok(vec![file(1, FileMode::NoClobber, "f")]));
+        assert_eq!(resolve(Operator::Clobber), Ok(vec![file(1, FileMode::Write, "f")]));
+        assert_eq!(resolve(Operator::DGreat), Ok(vec![file(1, FileMode::Append, "f")]));
+        assert_eq!(
+            resolve(Operator::AndGreat),
+            Ok(vec![file(1, FileMode::NoClobber, "f"), Redirection::Dup { fd: 2, source: 1 }])
+        );
+    }
+
+    #[test]
+    fn both_outputs_to_a_file() {
+        let both = |mode| Ok(vec![file(1, mode, "all"), Redirection::Dup { fd: 2, source: 1 }]);
+        assert_eq!(resolve(Operator::AndGreat, None, "all"), both(FileMode::Write));
+        assert_eq!(resolve(Operator::AndDGreat, None, "all"), both(FileMode::Append));
+        assert_eq!(resolve(Operator::GreatAnd, None, "all"), both(FileMode::Write));
+    }
+
+    #[test]
+    fn duplicating_and_closing_descriptors() {
+        assert_eq!(resolve(Operator::GreatAnd, Some(2), "1"), Ok(vec![Redirection::Dup { fd: 2, source: 1 }]));
+        assert_eq!(resolve(Operator::LessAnd, None, "3"), Ok(vec![Redirection::Dup { fd: 0, source: 3 }]));
+        assert_eq!(resolve(Operator::GreatAnd, Some(2), "-"), Ok(vec![Redirection::Close { fd: 2 }]));
+        assert_eq!(resolve(Operator::LessAnd, None, "-"), Ok(vec![Redirection::Close { fd: 0 }]));
+    }
+
+    #[test]
+    fn duplicating_to_a_word_other_than_stdout_is_ambiguous() {
+        assert_eq!(resolve(Operator::GreatAnd, Some(2), "file"), Err("file: ambiguous redirect".to_string()));
+        assert_eq!(resolve(Operator::LessAnd, None, "file"), Err("file: ambiguous redirect".to_string()));
+    }
+
+    #[test]
+    fn here_documents_and_strings_feed_text() {
+        let here = |fd, text: &str| Ok(vec![Redirection::Here { fd, text: text.to_string() }]);
+        assert_eq!(resolve(Operator::DLess, None, "body\n"), here(0, "body\n"));
+        assert_eq!(resolve(Operator::DLessDash, Some(3), "body\n"), here(3, "body\n"));
+        assert_eq!(resolve(Operator::TLess, None, "word\n"), here(0, "word\n"));
+    }
+
+    #[test]
+    fn control_operators_are_not_redirections() {
+        assert!(resolve(Operator::Pipe, None, "x").is_err());
     }
 }
@@ -0,0 +1,102 @@
+use nix::sys::wait::WaitStatus;
+
+use crate::background::BackgroundManager;
+use crate::environment::Variables;
+use crate::trap::Traps;
+
+/// Behaviour switches toggled by `set -o` and `shopt`
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// A pipeline's status is that of its rightmost failing command
+    pub pipefail: bool,
+    /// `>` refuses to overwrite an existing file; `>|` still can
+    pub noclobber: bool,
+    /// Report finished background jobs as soon as they finish, not at the next prompt
+    pub notify: bool,
+    /// Patterns that match nothing expand to no words at all
+    pub nullglob: bool,
+    /// Patterns that match nothing are an error
+    pub failglob: bool,
+    /// `**` matches across directory levels
+    pub globstar: bool,
+}
+
+/// State shared by the REPL, the executor and the builtins
+pub struct Shell {
+    pub bg_manager: BackgroundManager,
+    pub history: Vec<String>,
+    /// Shell variables; the exported ones form the environment of child processes
+    pub vars: Variables,
+    pub options: Options,
+    /// Commands set with `trap`
+    pub traps: Traps,
+    /// Jobs get their own process groups and the foreground one gets the terminal;
+    /// only on in an interactive shell
+    pub job_control: bool,
+    /// Process id of the shell, expanded by `$$`. Taken at startup, so subshells
+    /// report the shell's pid rather than their own.
+    pub pid: u32,
+    /// Exit status of the most recent foreground pipeline, expanded by `$?`
+    pub last_status: i32,
+    /// Exit status of each command in the most recent foreground pipeline
+    pub pipestatus: Vec<i32>,
+    /// Exit status of the last command substitution run while expanding the current
+    /// command, which becomes the status of a command made only of assignments
+    pub substitution_status: Option<i32>,
+    /// Set by the `exit` builtin; the REPL stops once the current line finishes
+    pub exit_requested: bool,
+    /// The user was told about stopped jobs when trying to leave; the next attempt
+    /// goes ahead
+    pub stopped_jobs_warned: bool,
+    /// This is a forked copy of the shell, running a command substitution, a pipeline
+    /// stage or a background list; `exit` ends only this process
+    pub subshell: bool,
+}
+
+impl Shell {
+    pub fn new() -> Self {
+        Self {
+            bg_manager: BackgroundManager::new(),
+            history: Vec::new(),
+            vars: Variables::from_env(),
+            options: Options::default(),
+            traps: Traps::default(),
+            job_control: false,
+            pid: std::process::id(),
+            last_status: 0,
+            pipestatus: vec![0],
+            substitution_status: None,
+            exit_requested: false,
+            stopped_jobs_warned: false,
+            subshell: false,
+        }
+    }
+
+    /// Whether the shell may leave now, by `exit` or end of input. Stopped jobs would
+    /// be stranded, so the first attempt with any only warns about them.
+    pub fn may_leave(&mut self) -> bool {
+        if !self.job_control || self.stopped_jobs_warned || !self.bg_manager.has_stopped_jobs() {
+            return true;
+        }
+        eprintln!("There are stopped jobs.");
+        self.stopped_jobs_warned = true;
+        false
+    }
+
+    /// Make this forked copy of the shell a subshell: it keeps no traps but ignored
+    /// signals, and its `exit` leaves the parent shell alone
+    pub fn enter_subshell(&mut self) {
+        self.subshell = true;
+        self.traps.reset_in_subshell();
+    }
+}
+
+/// Convert a wait status into a shell exit code: the exit value, or 128 + signal number
+pub fn exit_code(status: WaitStatus) -> i32 {
+    match status {
+        WaitStatus::Exited(_, code) => code,
+        WaitStatus::Signaled(_, signal, _) => 128 + signal as i32,
+        WaitStatus::Stopped(_, signal) => 128 + signal as i32,
+        _ => 0,
+    }
+}
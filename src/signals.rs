@@ -0,0 +1,213 @@
+use nix::libc::c_int;
+use nix::sys::signal::{SaFlags, SigAction, SigHandler, SigSet, Signal, sigaction, signal};
+use signal_hook::SigId;
+use std::io::{self, Read};
+use std::os::unix::net::UnixStream;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+/// Set when the interactive shell receives SIGINT; the prompt and the `wait` builtin
+/// check and clear it
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Read end of a self-pipe that gets a byte on every SIGCHLD, so the prompt can wake
+/// up and reap children while it waits for input
+static CHILD_PIPE: OnceLock<UnixStream> = OnceLock::new();
+
+/// Signal numbers the trap handler can record; the standard signals are 1 to 31
+const SIGNAL_SLOTS: usize = 32;
+
+/// Set by `note_signal` when a trapped signal arrives, until its trap has run
+static PENDING: [AtomicBool; SIGNAL_SLOTS] = [const { AtomicBool::new(false) }; SIGNAL_SLOTS];
+
+/// Signals ignored with `trap '' SIG`, which the commands the shell runs inherit
+static TRAP_IGNORED: [AtomicBool; SIGNAL_SLOTS] = [const { AtomicBool::new(false) }; SIGNAL_SLOTS];
+
+/// Signals an interactive shell must survive; they stay meant for the foreground job
+const IGNORED: &[Signal] = &[Signal::SIGQUIT, Signal::SIGTSTP, Signal::SIGTTIN, Signal::SIGTTOU];
+
+/// Signals whose disposition the shell changes, and so must reset in its children
+const HANDLED: &[Signal] = &[
+    Signal::SIGINT,
+    Signal::SIGQUIT,
+    Signal::SIGTSTP,
+    Signal::SIGTTIN,
+    Signal::SIGTTOU,
+    Signal::SIGCHLD,
+    // Ignored by the Rust runtime before main
+    Signal::SIGPIPE,
+];
+
+/// Set up an interactive shell's signals: Ctrl-C only interrupts the line being typed,
+/// Ctrl-\ and Ctrl-Z are ignored, and SIGCHLD wakes up the prompt
+pub fn init_signals() {
+    for &ignored in IGNORED {
+        // SAFETY: ignoring a signal installs no handler code
+        let _ = unsafe { signal(ignored, SigHandler::SigIgn) };
+    }
+    // Not restarted, so a blocking wait fails with EINTR and can give up
+    let action = SigAction::new(SigHandler::Handler(note_interrupt), SaFlags::empty(), SigSet::empty());
+    // SAFETY: note_interrupt only stores to an atomic
+    if let Err(e) = unsafe { sigaction(Signal::SIGINT, &action) } {
+        eprintln!("warning: cannot catch SIGINT: {}", e);
+    }
+
+    let registered = UnixStream::pair().and_then(|(reader, writer)| {
+        reader.set_nonblocking(true)?;
+        signal_hook::low_level::pipe::register(Signal::SIGCHLD as i32, writer)?;
+        Ok(reader)
+    });
+    match registered {
+        Ok(reader) => {
+            let _ = CHILD_PIPE.set(reader);
+        }
+        Err(e) => eprintln!("warning: cannot catch SIGCHLD: {}", e),
+    }
+}
+
+/// The pipe that becomes readable when a child has changed state, once `init_signals`
+/// has set it up
+pub fn child_pipe() -> Option<&'static UnixStream> {
+    CHILD_PIPE.get()
+}
+
+/// Empty the SIGCHLD pipe once the wake-up has been handled
+pub fn drain_child_pipe() {
+    if let Some(mut pipe) = CHILD_PIPE.get() {
+        let mut buf = [0u8; 64];
+        while matches!(pipe.read(&mut buf), Ok(n) if n > 0) {}
+    }
+}
+
+/// True if SIGINT arrived since the last call
+pub fn take_interrupt() -> bool {
+    INTERRUPTED.swap(false, Ordering::Relaxed)
+}
+
+/// Give a freshly forked child the default disposition of every signal the shell
+/// changed, before it runs a command. Signals ignored by a trap stay ignored.
+pub fn reset_child_signals() {
+    for &handled in HANDLED {
+        if TRAP_IGNORED[handled as usize].load(Ordering::Relaxed) {
+            continue;
+        }
+        // SAFETY: restoring the default disposition installs no handler code
+        let _ = unsafe { signal(handled, SigHandler::SigDfl) };
+    }
+}
+
+/// SIGINT handler of the interactive shell: only records the interrupt
+extern "C" fn note_interrupt(_: c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// Handler for trapped signals: only records the signal, since the trap's command
+/// must wait for the shell to reach a safe point
+extern "C" fn note_signal(signo: c_int) {
+    if let Some(pending) = PENDING.get(signo as usize) {
+        pending.store(true, Ordering::Relaxed);
+    }
+    // A trapped Ctrl-C still abandons the line being typed
+    if signo == Signal::SIGINT as c_int {
+        INTERRUPTED.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Catch `signal` so that its trap runs at the next safe point. Returns the
+/// disposition it replaces.
+pub fn trap_signal(signal: Signal) -> nix::Result<SigAction> {
+    // Restarting keeps reads and waits from failing; the prompt's poll() still wakes up.
+    // SIGINT is the exception, as it must still be able to interrupt a wait.
+    let flags = if signal == Signal::SIGINT { SaFlags::empty() } else { SaFlags::SA_RESTART };
+    let action = SigAction::new(SigHandler::Handler(note_signal), flags, SigSet::empty());
+    // SAFETY: note_signal only stores to atomics
+    let previous = unsafe { sigaction(signal, &action) }?;
+    TRAP_IGNORED[signal as usize].store(false, Ordering::Relaxed);
+    Ok(previous)
+}
+
+/// Ignore `signal`, in the shell and in the commands it runs. Returns the disposition
+/// it replaces.
+pub fn ignore_signal(signal: Signal) -> nix::Result<SigAction> {
+    let action = SigAction::new(SigHandler::SigIgn, SaFlags::empty(), SigSet::empty());
+    // SAFETY: ignoring a signal installs no handler code
+    let previous = unsafe { sigaction(signal, &action) }?;
+    TRAP_IGNORED[signal as usize].store(true, Ordering::Relaxed);
+    Ok(previous)
+}
+
+/// Catch SIGCHLD for a trap. The shell's own handler, which reaps and reports jobs,
+/// stays in place and the trap's handler runs beside it. Returns the id that
+/// `untrap_child_signal` takes.
+pub fn trap_child_signal() -> io::Result<SigId> {
+    let pending = &PENDING[Signal::SIGCHLD as usize];
+    // SAFETY: the action only stores to an atomic
+    unsafe { signal_hook::low_level::register(Signal::SIGCHLD as c_int, move || pending.store(true, Ordering::Relaxed)) }
+}
+
+/// Remove a SIGCHLD trap handler added by `trap_child_signal`
+pub fn untrap_child_signal(id: SigId) {
+    signal_hook::low_level::unregister(id);
+    PENDING[Signal::SIGCHLD as usize].store(false, Ordering::Relaxed);
+}
+
+/// Put back a disposition replaced by `trap_signal` or `ignore_signal`
+pub fn restore_signal(signal: Signal, original: &SigAction) {
+    // SAFETY: the action is one the shell had installed before
+    let _ = unsafe { sigaction(signal, original) };
+    TRAP_IGNORED[signal as usize].store(false, Ordering::Relaxed);
+    PENDING[signal as usize].store(false, Ordering::Relaxed);
+}
+
+/// Forget that `signal` arrived, if its trap has not run yet
+pub fn clear_pending_signal(signal: Signal) {
+    PENDING[signal as usize].store(false, Ordering::Relaxed);
+}
+
+/// The trapped signals that arrived since the last call, lowest number first
+pub fn take_pending_signals() -> Vec<Signal> {
+    Signal::iterator()
+        .filter(|&signal| PENDING.get(signal as usize).is_some_and(|p| p.swap(false, Ordering::Relaxed)))
+        .collect()
+}
+
+/// Parse a signal given by number or by name, with or without the SIG prefix and in
+/// any case, e.g. `9`, `KILL`, `SIGKILL` or `kill`
+pub fn parse_signal(spec: &str) -> Option<Signal> {
+    if let Ok(number) = spec.parse::<i32>() {
+        return Signal::try_from(number).ok();
+    }
+    let upper = spec.to_ascii_uppercase();
+    let name = if upper.starts_with("SIG") { upper } else { format!("SIG{}", upper) };
+    Signal::from_str(&name).ok()
+}
+
+/// A signal's name without the SIG prefix, as `kill -l` prints it
+pub fn signal_name(signal: Signal) -> &'static str {
+    &signal.as_str()[3..]
+}
+
+/// How a job killed by `signal` is described, following strsignal(3)
+pub fn describe_signal(signal: Signal) -> &'static str {
+    match signal {
+        Signal::SIGHUP => "Hangup",
+        Signal::SIGINT => "Interrupt",
+        Signal::SIGQUIT => "Quit",
+        Signal::SIGILL => "Illegal instruction",
+        Signal::SIGTRAP => "Trace/breakpoint trap",
+        Signal::SIGABRT => "Aborted",
+        Signal::SIGBUS => "Bus error",
+        Signal::SIGFPE => "Floating point exception",
+        Signal::SIGKILL => "Killed",
+        Signal::SIGUSR1 => "User defined signal 1",
+        Signal::SIGSEGV => "Segmentation fault",
+        Signal::SIGUSR2 => "User defined signal 2",
+        Signal::SIGPIPE => "Broken pipe",
+        Signal::SIGALRM => "Alarm clock",
+        Signal::SIGTERM => "Terminated",
+        Signal::SIGXCPU => "CPU time limit exceeded",
+        Signal::SIGXFSZ => "File size limit exceeded",
+        _ => signal.as_str(),
+    }
+}
@@ -1,12 +1,20 @@
-use std::env;
+use crate::lexer::{Quote, Word};
+use crate::shell::Shell;
 
-pub fn expand_tilde(token: &str) -> String {
-    if token == "~" {
-        env::var("HOME").unwrap_or_else(|_| String::from("~"))
-    } else if token.starts_with("~/") {
-        let home = env::var("HOME").unwrap_or_else(|_| String::from("~"));
-        format!("{}/{}", home, &token[2..])
-    } else {
-        token.to_string()
+/// Expand a leading `~` or `~/`, but only when the tilde itself was not quoted
+pub fn expand_tilde(word: &Word, shell: &Shell) -> Word {
+    let mut word = word.clone();
+    let single_part = word.parts.len() == 1;
+    if let Some(first) = word.parts.first_mut() {
+        if first.quote != Quote::None {
+            return word;
+        }
+        let home = shell.vars.get("HOME").unwrap_or("~").to_string();
+        if first.text == "~" && single_part {
+            first.text = home;
+        } else if let Some(rest) = first.text.strip_prefix("~/") {
+            first.text = format!("{}/{}", home, rest);
+        }
     }
+    word
 }
@@ -0,0 +1,262 @@
+use nix::sys::signal::{SigAction, Signal};
+use std::collections::BTreeMap;
+
+use crate::executor::execute_list;
+use crate::lexer::get_tokens;
+use crate::parser::parse;
+use crate::shell::Shell;
+use crate::signals::{
+    clear_pending_signal, ignore_signal, parse_signal, restore_signal, signal_name, take_pending_signals,
+    trap_child_signal, trap_signal, untrap_child_signal,
+};
+use signal_hook::SigId;
+
+/// What a trap is set on: leaving the shell, a failing command, or a signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    Exit,
+    Err,
+    Signal(Signal),
+}
+
+impl Condition {
+    /// Parse `EXIT` (or 0), `ERR`, or a signal name or number, in any case
+    pub fn parse(spec: &str) -> Option<Self> {
+        match spec.to_ascii_uppercase().as_str() {
+            "EXIT" | "SIGEXIT" | "0" => Some(Condition::Exit),
+            "ERR" | "SIGERR" => Some(Condition::Err),
+            _ => parse_signal(spec).map(Condition::Signal),
+        }
+    }
+
+    /// The name `trap -p` prints
+    fn name(&self) -> &'static str {
+        match self {
+            Condition::Exit => "EXIT",
+            Condition::Err => "ERR",
+            Condition::Signal(signal) => signal.as_str(),
+        }
+    }
+}
+
+/// A trapped signal: its command, empty if the signal is ignored, and how the trap
+/// was put in place
+struct SignalTrap {
+    command: String,
+    hook: Hook,
+}
+
+/// How a signal trap was installed, so that removing it can undo exactly that
+enum Hook {
+    /// The trap replaced this disposition
+    Replaced(SigAction),
+    /// SIGCHLD keeps the shell's handler, as the jobs depend on it. A trap with a
+    /// command adds a handler beside it; an ignoring one is only recorded, since an
+    /// ignored SIGCHLD would have the kernel reap children before the shell can wait.
+    Chained(Option<SigId>),
+}
+
+/// Commands set with `trap`
+#[derive(Default)]
+pub struct Traps {
+    exit: Option<String>,
+    err: Option<String>,
+    /// By signal number, so they are listed in order
+    signals: BTreeMap<i32, SignalTrap>,
+    /// Set while a trap's command runs; traps do not go off inside one another
+    running: bool,
+}
+
+impl Traps {
+    /// Set the command run on `condition`. An empty command ignores a signal.
+    pub fn set(&mut self, condition: Condition, command: &str) -> Result<(), String> {
+        match condition {
+            Condition::Exit => self.exit = Some(command.to_string()),
+            Condition::Err => self.err = Some(command.to_string()),
+            Condition::Signal(Signal::SIGCHLD) => {
+                let id = if command.is_empty() {
+                    None
+                } else {
+                    Some(trap_child_signal().map_err(|e| format!("{}: {}", signal_name(Signal::SIGCHLD), e))?)
+                };
+                self.reset(condition);
+                let hook = Hook::Chained(id);
+                self.signals.insert(Signal::SIGCHLD as i32, SignalTrap { command: command.to_string(), hook });
+            }
+            Condition::Signal(signal) => {
+                let replaced = if command.is_empty() { ignore_signal(signal) } else { trap_signal(signal) }
+                    .map_err(|e| format!("{}: {}", signal_name(signal), e.desc()))?;
+                // Keep the shell's own disposition from before the first trap
+                let original = match self.signals.remove(&(signal as i32)) {
+                    Some(SignalTrap { hook: Hook::Replaced(original), .. }) => original,
+                    _ => replaced,
+                };
+                let hook = Hook::Replaced(original);
+                self.signals.insert(signal as i32, SignalTrap { command: command.to_string(), hook });
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the trap on `condition`, giving a signal back the disposition it had before
+    pub fn reset(&mut self, condition: Condition) {
+        match condition {
+            Condition::Exit => self.exit = None,
+            Condition::Err => self.err = None,
+            Condition::Signal(signal) => match self.signals.remove(&(signal as i32)).map(|trap| trap.hook) {
+                Some(Hook::Replaced(original)) => restore_signal(signal, &original),
+                Some(Hook::Chained(Some(id))) => untrap_child_signal(id),
+                Some(Hook::Chained(None)) | None => {}
+            },
+        }
+    }
+
+    /// The command set on `condition`, if any
+    pub fn command(&self, condition: Condition) -> Option<&str> {
+        match condition {
+            Condition::Exit => self.exit.as_deref(),
+            Condition::Err => self.err.as_deref(),
+            Condition::Signal(signal) => self.signals.get(&(signal as i32)).map(|t| t.command.as_str()),
+        }
+    }
+
+    /// Every condition with a trap set, EXIT first
+    pub fn conditions(&self) -> Vec<Condition> {
+        let mut conditions = Vec::new();
+        if self.exit.is_some() {
+            conditions.push(Condition::Exit);
+        }
+        conditions.extend(
+            self.signals
+                .keys()
+                .filter_map(|&number| Signal::try_from(number).ok())
+                .map(Condition::Signal),
+        );
+        if self.err.is_some() {
+            conditions.push(Condition::Err);
+        }
+        conditions
+    }
+
+    /// Print the trap on `condition` as a command that sets it again
+    pub fn print(&self, condition: Condition) {
+        if let Some(command) = self.command(condition) {
+            println!("trap -- '{}' {}", command.replace('\'', "'\\''"), condition.name());
+        }
+    }
+
+    /// Drop the traps a child shell does not inherit: all but the ignored signals
+    pub fn reset_in_subshell(&mut self) {
+        self.exit = None;
+        self.err = None;
+        let caught: Vec<i32> = self
+            .signals
+            .iter()
+            .filter(|(_, trap)| !trap.command.is_empty())
+            .map(|(&number, _)| number)
+            .collect();
+        for number in caught {
+            if let Ok(signal) = Signal::try_from(number) {
+                self.reset(Condition::Signal(signal));
+            }
+        }
+    }
+}
+
+/// Run the traps of the signals that arrived since the last safe point. Returns
+/// whether any trap ran, as it may have printed something.
+pub fn run_pending_traps(shell: &mut Shell) -> bool {
+    if shell.traps.running {
+        return false;
+    }
+    let mut ran = false;
+    for signal in take_pending_signals() {
+        if let Some(command) = shell.traps.command(Condition::Signal(signal)).map(str::to_string) {
+            run_trap(&command, shell);
+            ran = true;
+            // The commands of a SIGCHLD trap do not set it off again as they finish
+            if signal == Signal::SIGCHLD {
+                clear_pending_signal(signal);
+            }
+        }
+    }
+    ran
+}
+
+/// Run the EXIT trap, once, as the shell is about to exit
+pub fn run_exit_trap(shell: &mut Shell) {
+    if let Some(command) = shell.traps.exit.take() {
+        run_trap(&command, shell);
+    }
+}
+
+/// Run the ERR trap after a command failed
+pub fn run_err_trap(shell: &mut Shell) {
+    if shell.traps.running {
+        return;
+    }
+    if let Some(command) = shell.traps.err.clone() {
+        run_trap(&command, shell);
+    }
+}
+
+/// Run a trap's command. `$?` is left as it was before the trap went off, unless the
+/// command ran `exit`, whose status the shell then exits with.
+fn run_trap(command: &str, shell: &mut Shell) {
+    let list = match get_tokens(command).and_then(|tokens| parse(&tokens.items)) {
+        Ok(list) => list,
+        Err(err) => {
+            eprintln!("trap: {}", err);
+            return;
+        }
+    };
+
+    let status = shell.last_status;
+    let running = std::mem::replace(&mut shell.traps.running, true);
+    execute_list(&list, shell);
+    shell.traps.running = running;
+    if !shell.exit_requested {
+        shell.last_status = status;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_exit_and_err_in_any_case() {
+        for spec in ["EXIT", "exit", "SIGEXIT", "0"] {
+            assert_eq!(Condition::parse(spec), Some(Condition::Exit), "{}", spec);
+        }
+        for spec in ["ERR", "err", "SIGERR"] {
+            assert_eq!(Condition::parse(spec), Some(Condition::Err), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn parse_signals_by_name_or_number() {
+        for spec in ["INT", "int", "SIGINT", "sigint", "2"] {
+            assert_eq!(Condition::parse(spec), Some(Condition::Signal(Signal::SIGINT)), "{}", spec);
+        }
+        assert_eq!(Condition::parse("USR1"), Some(Condition::Signal(Signal::SIGUSR1)));
+    }
+
+    #[test]
+    fn parse_rejects_unknown_conditions() {
+        for spec in ["", "BOGUS", "SIG", "99", "-1"] {
+            assert_eq!(Condition::parse(spec), None, "{}", spec);
+        }
+    }
+
+    #[test]
+    fn conditions_list_exit_first_and_err_last() {
+        let mut traps = Traps::default();
+        traps.set(Condition::Err, "e").unwrap();
+        traps.set(Condition::Exit, "x").unwrap();
+        assert_eq!(traps.conditions(), vec![Condition::Exit, Condition::Err]);
+        traps.reset(Condition::Exit);
+        assert_eq!(traps.conditions(), vec![Condition::Err]);
+        assert_eq!(traps.command(Condition::Exit), None);
+    }
+}
@@ -0,0 +1,79 @@
+//! Scripts fed to the shell binary on stdin, checked by their output and side effects
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// A fresh, empty working directory for one test
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("shell-test-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Run `script` in `dir` and return what the shell wrote to stdout, prompts included
+fn run(dir: &PathBuf, script: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_project1-group-23"))
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(script.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn redirect_only_pipeline_stage_applies_its_redirections() {
+    let dir = scratch_dir("redirect-only-stage");
+    let out = run(&dir, "echo a | > out\necho st=$?\n");
+    assert!(out.contains("st=0\n"), "{}", out);
+    assert_eq!(fs::read_to_string(dir.join("out")).unwrap(), "");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn empty_pipeline_stage_runs_nothing() {
+    let dir = scratch_dir("empty-stage");
+    let out = run(&dir, "$EMPTY | cat\necho st=$?\necho x | $EMPTY\necho st=$?\n");
+    assert_eq!(out.matches("st=0\n").count(), 2, "{}", out);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn trapping_sigchld_keeps_command_statuses() {
+    let dir = scratch_dir("trap-chld");
+    let out = run(&dir, "trap '' CHLD\ntrue; echo st=$?\ntrap 'echo x > ran' CHLD\nfalse; echo st=$?\n");
+    assert!(out.contains("st=0\n"), "{}", out);
+    assert!(out.contains("st=1\n"), "{}", out);
+    assert!(dir.join("ran").exists());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn dollar_dollar_is_the_shell_pid_in_subshells() {
+    let dir = scratch_dir("dollar-dollar");
+    let out = run(&dir, "echo $$ $(echo $$) > pids\necho $$ | cat >> pids\n");
+    let pids = fs::read_to_string(dir.join("pids")).unwrap();
+    let pids: Vec<&str> = pids.split_whitespace().collect();
+    assert_eq!(pids.len(), 3, "{}", out);
+    assert!(pids.iter().all(|pid| *pid == pids[0]), "{:?}", pids);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn redirect_only_command_creates_and_truncates_files() {
+    let dir = scratch_dir("redirect-only-command");
+    fs::write(dir.join("old"), "text").unwrap();
+    let out = run(&dir, "> new\necho st=$?\n> old\nA=1 >> appended\n$EMPTY > expanded\necho $A\n");
+    assert!(out.contains("st=0\n"), "{}", out);
+    assert!(out.contains("1\n"), "{}", out);
+    for name in ["new", "old", "appended", "expanded"] {
+        assert_eq!(fs::read_to_string(dir.join(name)).unwrap(), "", "{}", name);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}